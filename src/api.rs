@@ -1,24 +1,113 @@
-use super::{CONFIG, CONTEST_LIST, JOB_LIST, MYSQL, USER_LIST};
+use super::{
+    ABORT, CONFIG, CONTEST_LIST, CREDENTIALS, JOB_LIST, JUDGE_QUEUE, RATE_LIMITER, REJUDGE_LIST,
+    RUNNING, SHUTDOWN, STORAGE, USER_LIST, WATCHERS,
+};
 use crate::{
-    config::{fread, Config, Error, ProblemCase, ProblemType},
+    auth::{authorize, holds, Credential, ADMIN, ANYONE, STAFF},
+    config::{fread, Config, Language, ProblemCase, ProblemType, Visibility},
+    error::Error,
+    limit::client_ip,
+    storage::StorageError,
+    store::{JobStore, UserStore},
     structs::{
-        later, string2result, string2state, Case, Contest, ContestArgs, JobArgs, JobRequest,
-        JobResponse, MyResult, State, User,
+        later, string2ids, string2results, string2state, Attempt, Case, Contest, ContestArgs,
+        JobArgs, JobRequest, JobResponse, MyResult, ProblemStatement, Rejudge, RejudgedJob, Role,
+        Sample, State, User,
     },
+    version::Version,
+};
+use actix_web::{
+    delete,
+    error::{JsonPayloadError, QueryPayloadError},
+    get,
+    http::header,
+    http::StatusCode,
+    post, put, web, HttpRequest, HttpResponse, Responder,
 };
-use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
 use chrono::{NaiveDateTime, Utc};
+use futures_util::stream;
 use log;
-use mysql::prelude::*;
-use mysql::*;
 use std::{
+    convert::Infallible,
     fs::File,
     io::Write,
     process::{Command, Stdio},
+    sync::atomic::Ordering,
     time::{Duration, Instant},
 };
+use tokio::sync::mpsc;
 use wait_timeout::ChildExt;
 
+const NOT_PERSISTED: &str = "no database configured, not persisted";
+
+/*
+    function: to report a failed write to storage, the lists in memory are left unchanged
+    input: err: the error from storage
+    output: an Error of ERR_INTERNAL
+*/
+fn storage_failure(err: StorageError) -> Error {
+    log::error!("Fail to write to storage: {}", err);
+    Error::internal(String::from("Fail to write to storage."))
+}
+
+/*
+    function: to refuse a request body that cannot be read as json with an Error
+    input: err: the JsonPayloadError of the body
+           _req: the &HttpRequest of the body
+    output: an actix_web::Error of ERR_INVALID_ARGUMENT, answered with 413 if the body is too large
+*/
+pub fn payload_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        JsonPayloadError::OverflowKnownLength { limit, .. }
+        | JsonPayloadError::Overflow { limit } => {
+            Error::invalid_argument(format!("Request body is larger than {} bytes.", limit))
+                .with_status(StatusCode::PAYLOAD_TOO_LARGE)
+        }
+        JsonPayloadError::ContentType => {
+            Error::invalid_argument(String::from("Request body must be application/json."))
+        }
+        err => Error::invalid_argument(format!("Invalid request body: {}", err)),
+    }
+    .into()
+} // the body is refused before it is parsed
+
+/*
+    function: to refuse a query string that cannot be read with an Error
+    input: err: the QueryPayloadError of the query
+           _req: the &HttpRequest of the query
+    output: an actix_web::Error of ERR_INVALID_ARGUMENT
+*/
+pub fn query_error(err: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    Error::invalid_argument(format!("Invalid query: {}", err)).into()
+}
+
+/*
+    function: to check the source code of a submission
+    input: source: a &str of the source code
+           lang: a &Language it is written in
+    output: Ok(()) if it could be judged, otherwise Err(why not)
+*/
+fn source_check(source: &str, lang: &Language) -> Result<(), String> {
+    let limit = lang.max_source_size.unwrap_or(65536);
+    if source.trim().is_empty() {
+        Err(String::from("Source code is empty."))
+    } else if source.len() > limit {
+        Err(format!(
+            "Source code of {} bytes exceeds the limit of {} bytes for {}.",
+            source.len(),
+            limit,
+            lang.name
+        ))
+    } else if source
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c'))
+    {
+        Err(String::from("Source code is binary."))
+    } else {
+        Ok(())
+    }
+} // a NUL or other control character outside whitespace means it is not text
+
 #[get("/hello/{name}")]
 pub async fn greet(name: web::Path<String>) -> impl Responder {
     log::info!(target: "greet_handler", "Greeting {}", name);
@@ -28,10 +117,88 @@ pub async fn greet(name: web::Path<String>) -> impl Responder {
 // DO NOT REMOVE: used in automatic testing
 #[post("/internal/exit")]
 #[allow(unreachable_code)]
-pub async fn exit() -> impl Responder {
+pub async fn exit(req: HttpRequest) -> Result<HttpResponse, Error> {
+    authorize(&req, ADMIN)?;
     log::info!("Shutdown as requested");
+    if let Some(storage) = STORAGE.lock().unwrap().as_mut() {
+        if let Err(err) = storage.checkpoint() {
+            log::warn!("Fail to write a checkpoint: {}", err);
+        }
+    }
     std::process::exit(0);
-    format!("Exited")
+    Ok(HttpResponse::Ok().body("Exited"))
+}
+/*
+    function: to re-read the config file and swap it in if it is still valid
+    input: none
+    output: Ok(()) if swapped, otherwise an Error telling why the reload was rejected
+*/
+pub fn reload_config() -> std::result::Result<(), Error> {
+    let config = &mut CONFIG.lock().unwrap();
+    let ctlist = &mut CONTEST_LIST.lock().unwrap();
+    let joblist = &JOB_LIST.lock().unwrap();
+    let mut new = match crate::config::config(&crate::config::args()) {
+        Ok(new) => new,
+        Err(reason) => return Err(Error::invalid_argument(reason)),
+    };
+    // parse the config file again, checking its input and answer files
+    let exists = |pid: usize| new.problems.iter().any(|problem| problem.id == pid);
+    for job in joblist.iter() {
+        if !exists(job.submission.problem_id) {
+            return Err(Error::invalid_state(format!(
+                "Problem {} of job {} is missing in the new config.",
+                job.submission.problem_id, job.id
+            )));
+        }
+    }
+    for ct in ctlist.iter().filter(|ct| ct.id != Some(0)) {
+        for pid in &ct.problem_ids {
+            if !exists(*pid) {
+                return Err(Error::invalid_state(format!(
+                    "Problem {} of contest {} is missing in the new config.",
+                    pid,
+                    ct.id.unwrap()
+                )));
+            }
+        }
+    }
+    // refuse to orphan the problems of existing jobs and contests
+    new.flush = config.flush;
+    if new.server != config.server {
+        log::warn!("Changes to the server section take effect after a restart");
+        new.server = config.server.clone();
+    }
+    // the listening sockets are already bound
+    if new.database != config.database {
+        log::warn!("Changes to the database section take effect after a restart");
+        new.database = config.database.clone();
+    }
+    // the database pool is already connected
+    if new.auth != config.auth {
+        log::warn!("Changes to the auth section take effect after a restart");
+        new.auth = config.auth.clone();
+    }
+    // root's password is only set at startup
+    ctlist.get_mut(0).unwrap().problem_ids =
+        new.problems.iter().map(|problem| problem.id).collect();
+    // contest0 holds all problems
+    **config = new;
+    log::info!("Config reloaded");
+    Ok(())
+}
+/*
+    function: to reload the config file as requested
+    input: req: a HttpRequest bearing the token
+    output: Responder
+*/
+#[post("/internal/reload")]
+pub async fn reload(req: HttpRequest) -> Result<HttpResponse, Error> {
+    authorize(&req, ADMIN)?;
+    if let Err(err) = reload_config() {
+        log::warn!("Config reload rejected: {}", err.message);
+        return Err(err.with_status(StatusCode::BAD_REQUEST));
+    }
+    Ok(HttpResponse::Ok().json(&*CONFIG.lock().unwrap()))
 }
 /*
     function: to check the ans and out in standard mode, along with an empty info
@@ -118,6 +285,25 @@ async fn check(ptype: &ProblemType, ans: &str, spj: Option<Vec<String>>) -> (Opt
         ProblemType::DynamicRanking => standard_check(ans).await,
     }
 }
+/*
+    function: to describe the first difference between the output and the answer
+    input: ans: a &str of the path of answer
+    output: a String naming the first differing line, with the expected and the actual content
+*/
+fn diff_snippet(ans: &str) -> String {
+    let outtext = fread("tmpdir/test.out", "outtext").unwrap();
+    let anstext = fread(ans, "ansfile").unwrap();
+    let outlines = outtext.trim().split('\n').collect::<Vec<&str>>();
+    let anslines = anstext.trim().split('\n').collect::<Vec<&str>>();
+    for i in 0..anslines.len().max(outlines.len()) {
+        let expected = anslines.get(i).map(|line| line.trim()).unwrap_or("<EOF>");
+        let found = outlines.get(i).map(|line| line.trim()).unwrap_or("<EOF>");
+        if expected != found {
+            return format!("line {}: expected '{}', found '{}'", i + 1, expected, found);
+        }
+    }
+    String::from("output differs in whitespace")
+}
 /*
     function: to compile a rust programme
     input: code: a &str of the source code
@@ -238,6 +424,7 @@ async fn c_compile(code: &str) -> Case {
            id: a usize of the case'id
            ptype: a &ProblemType of the problem's type
            spj: a vec of String from misc related to the special judge
+           jobid: a usize of the job judged
     output: a Case
 */
 async fn testcase(
@@ -245,6 +432,7 @@ async fn testcase(
     id: usize,
     ptype: &ProblemType,
     spj: Option<Vec<String>>,
+    jobid: usize,
 ) -> Case {
     let in_file = File::open(case.input_file.clone()).expect("Fail to create out_file");
     let out_file = File::create("tmpdir/test.out").expect("Fail to create out_file");
@@ -260,10 +448,20 @@ async fn testcase(
         .stderr(Stdio::from(err_file))
         .spawn()
         .unwrap();
-    match child
-        .wait_timeout(limit + Duration::from_millis(500))
-        .unwrap()
-    {
+    let status = loop {
+        let left = (limit + Duration::from_millis(500)).saturating_sub(now.elapsed());
+        let status = child
+            .wait_timeout(left.min(Duration::from_millis(50)))
+            .unwrap();
+        if status.is_some() || left.is_zero() || canceled(jobid) {
+            break status;
+        }
+    }; // waited for in slices, to stop as soon as the job is canceled
+    if status.is_none() {
+        let _ = child.kill();
+        let _ = child.wait();
+    } // the programme over the time limit or canceled is not left running
+    match status {
         // check if the case exceed the time limit
         Some(_) => {
             if fread("tmpdir/test.err", "err_file").unwrap().is_empty() {
@@ -285,6 +483,10 @@ async fn testcase(
                     } else {
                         // Some(false)
                         result = MyResult::WA;
+                        if case.visibility == Some(Visibility::Sample) && info.is_empty() {
+                            // sample cases come with a snippet of the difference
+                            info = diff_snippet(&case.answer_file);
+                        }
                     }
                 }
             } else {
@@ -305,6 +507,45 @@ async fn testcase(
         info,
     }
 }
+/*
+    function: to check if a job has been canceled or deleted while judging,
+              or cut off by a shutdown
+    input: id: a usize of the job's id
+    output: true if the rest of the judging can be skipped
+*/
+fn canceled(id: usize) -> bool {
+    ABORT.load(Ordering::SeqCst)
+        || !matches!(JOB_LIST.lock().unwrap().get(id), Some(job) if job.state != State::Canceled)
+}
+/*
+    function: to tell the event streams of a job about its change
+    input: job: a &JobResponse as it is now in JOB_LIST, which is locked by the caller
+*/
+fn publish(job: &JobResponse) {
+    let watchers = &mut WATCHERS.lock().unwrap();
+    if let Some(senders) = watchers.get_mut(&job.id) {
+        senders.retain(|sender| sender.send(job.clone()).is_ok());
+        // the streams closed by their clients are dropped
+        if senders.is_empty() || job.state == State::Finished || job.state == State::Canceled {
+            watchers.remove(&job.id);
+        } // a job settled has nothing more to tell, its streams end
+    }
+}
+/*
+    function: to put a case just judged into a running job, so that it is seen before the job finishes
+    input: id: a usize of the job's id
+           case: a &Case of the compilation or of a test case
+*/
+fn progress(id: usize, case: &Case) {
+    let joblist = &mut JOB_LIST.lock().unwrap();
+    if let Some(job) = joblist.get_mut(id) {
+        if job.state == State::Running && case.id < job.cases.len() {
+            job.cases[case.id] = case.clone();
+            job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+            publish(job);
+        }
+    } // canceled or deleted meanwhile
+}
 /*
     function: to test a JobRequest
     input: req: a &JobRequest of this request
@@ -331,6 +572,7 @@ async fn process_post(req: &JobRequest, config: &Config, jobid: usize) -> JobRes
         "C" => c_compile(&req.source_code).await,
         _ => panic!("unexpected language"),
     }; // the panic will never occur since the language was checked in post_job
+    progress(jobid, &case0);
     let mut cases: Vec<Case> = vec![case0];
     if cases[0].result != MyResult::CS {
         // when compile err, all cases turn to waiting
@@ -358,13 +600,17 @@ async fn process_post(req: &JobRequest, config: &Config, jobid: usize) -> JobRes
             let pack = misc.unwrap().packing.as_ref().unwrap();
             let mut count = 0;
             for i in 0..pack.len() {
+                if canceled(jobid) {
+                    break;
+                }
                 let mut judge = true;
                 let mut packscore = 0.0;
                 for j in 0..pack[i].len() {
                     let problem_case = &config.problems[sub].cases[count + j];
                     if judge {
                         // the cases before was all accepted
-                        let case = testcase(problem_case, count + j + 1, ptype, spj.clone()).await;
+                        let case =
+                            testcase(problem_case, count + j + 1, ptype, spj.clone(), jobid).await;
                         if case.result == MyResult::Accepted {
                             // this case is accepted, add score
                             if config.problems[sub].ty == ProblemType::DynamicRanking {
@@ -396,6 +642,7 @@ async fn process_post(req: &JobRequest, config: &Config, jobid: usize) -> JobRes
                             info: String::from(""),
                         });
                     }
+                    progress(jobid, cases.last().unwrap());
                 }
                 if judge {
                     score += packscore;
@@ -405,8 +652,11 @@ async fn process_post(req: &JobRequest, config: &Config, jobid: usize) -> JobRes
         } else {
             // not packed judging
             for i in 0..config.problems[sub].cases.len() {
+                if canceled(jobid) {
+                    break;
+                }
                 let problem_case = &config.problems[sub].cases[i];
-                let post_case = testcase(problem_case, i + 1, ptype, spj.clone()).await;
+                let post_case = testcase(problem_case, i + 1, ptype, spj.clone(), jobid).await;
                 if post_case.result == MyResult::Accepted {
                     // this case is accepted, add score
                     if config.problems[sub].ty == ProblemType::DynamicRanking {
@@ -427,6 +677,7 @@ async fn process_post(req: &JobRequest, config: &Config, jobid: usize) -> JobRes
                     result = post_case.result.clone();
                     cases.push(post_case);
                 }
+                progress(jobid, cases.last().unwrap());
             }
         }
     }
@@ -456,15 +707,322 @@ async fn process_post(req: &JobRequest, config: &Config, jobid: usize) -> JobRes
         warning: None,
     }
 }
+/*
+    function: to build a job waiting in the judge queue
+    input: req: a &JobRequest of this job
+           config: a &Config of the global config
+           jobid: a usize of this job's id
+           created_time: a String of the time the job was first submitted
+    output: a JobResponse in Queueing, with the compilation and all cases Waiting
+*/
+pub fn queueing_job(
+    req: &JobRequest,
+    config: &Config,
+    jobid: usize,
+    created_time: String,
+) -> JobResponse {
+    let count = config
+        .problems
+        .iter()
+        .find(|problem| problem.id == req.problem_id)
+        .map_or(0, |problem| problem.cases.len());
+    let cases = (0..=count)
+        .map(|id| Case {
+            id,
+            result: MyResult::Waiting,
+            time: 0,
+            memory: 0.0,
+            info: String::from(""),
+        })
+        .collect();
+    JobResponse {
+        id: jobid,
+        created_time,
+        updated_time: Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+        submission: req.clone(),
+        state: State::Queueing,
+        result: MyResult::Waiting,
+        score: 0.0,
+        cases,
+        warning: None,
+    }
+}
+/*
+    function: to put a job at the end of the judge queue
+    input: id: a usize of the job's id
+*/
+pub fn enqueue(id: usize) {
+    let (queue, ready) = &**JUDGE_QUEUE;
+    queue.lock().unwrap().push_back(id);
+    ready.notify_one();
+}
+/*
+    function: to judge the queued jobs one by one, never returns
+    input: none
+*/
+pub fn judge_worker() {
+    let runtime = actix_web::rt::System::new();
+    loop {
+        let id = {
+            let (queue, ready) = &**JUDGE_QUEUE;
+            let mut queue = queue.lock().unwrap();
+            while queue.is_empty() || SHUTDOWN.load(Ordering::SeqCst) {
+                queue = ready.wait(queue).unwrap();
+            }
+            queue.pop_front().unwrap()
+        };
+        // wait for the next job, none is taken once shutting down
+        runtime.block_on(judge(id));
+    }
+}
+/*
+    function: to stop taking submissions and wait for the running job before shutting down
+    input: deadline: a Duration of the longest wait for the running job
+    output: false if a shutdown is already under way, true after draining
+*/
+pub async fn drain(deadline: Duration) -> bool {
+    if SHUTDOWN.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+    log::info!("Shutting down, waiting for the running job");
+    JUDGE_QUEUE.1.notify_all();
+    let start = Instant::now();
+    while RUNNING.lock().unwrap().is_some() && start.elapsed() < deadline {
+        actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+    }
+    let running = *RUNNING.lock().unwrap();
+    if let Some(id) = running {
+        ABORT.store(true, Ordering::SeqCst);
+        log::warn!("Job {} is still running after {:?}", id, deadline);
+    } // its programme is killed by the judge as it sees the abort
+      // the job is left running in storage and judged again after restart
+    let queued = JUDGE_QUEUE.0.lock().unwrap().len();
+    if queued > 0 {
+        log::info!("{} queued jobs are kept for the next start", queued);
+    }
+    WATCHERS.lock().unwrap().clear();
+    // the event streams end with the server
+    true
+}
+/*
+    function: to judge a job in the queue, storing its state before and after
+    input: id: a usize of the job's id
+*/
+async fn judge(id: usize) {
+    let config = CONFIG.lock().unwrap().clone();
+    // judge with a snapshot, so that the config stays available meanwhile
+    let req = {
+        let joblist = &mut JOB_LIST.lock().unwrap();
+        let job = match joblist.get_mut(id) {
+            Some(job) if job.state == State::Queueing => job,
+            _ => return,
+        };
+        // deleted while waiting
+        job.state = State::Running;
+        job.result = MyResult::Running;
+        job.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+        if let Some(storage) = STORAGE.lock().unwrap().as_mut() {
+            if let Err(err) = storage.update_job(job) {
+                log::error!("Fail to store job {} as running: {}", id, err);
+            }
+        }
+        publish(job);
+        job.submission.clone()
+    };
+    *RUNNING.lock().unwrap() = Some(id);
+    let js = process_post(&req, &config, id).await;
+    if ABORT.load(Ordering::SeqCst) {
+        log::warn!(
+            "Job {} is cut off by the shutdown, judged again after restart",
+            id
+        );
+    } else {
+        record(id, js);
+    }
+    *RUNNING.lock().unwrap() = None;
+    // cleared only after the result is stored, so a shutdown waiting for it loses nothing
+}
+/*
+    function: to store the result of a judging as a new attempt of the job
+    input: id: a usize of the job's id
+           js: a JobResponse of the result
+*/
+fn record(id: usize, mut js: JobResponse) {
+    let joblist = &mut JOB_LIST.lock().unwrap();
+    match joblist.get(id) {
+        Some(job) if job.state == State::Running => {}
+        _ => return,
+    } // deleted or canceled while judging
+    if let Some(job) = joblist.get_mut(id) {
+        js.created_time = job.created_time.clone();
+        js.warning = job.warning.clone();
+        *job = js.clone();
+    }
+    let attempt = Attempt {
+        job_id: id,
+        id: joblist.attempts(id).last().map_or(0, |last| last.id + 1),
+        judged_time: js.updated_time.clone(),
+        result: js.result.clone(),
+        score: js.score,
+        cases: js.cases.clone(),
+    };
+    if let Some(storage) = STORAGE.lock().unwrap().as_mut() {
+        if let Err(err) = storage.finish_job(&js, &attempt) {
+            log::error!("Fail to store the result of job {}: {}", id, err);
+        }
+    }
+    // a job left running in storage is judged again after restart
+    joblist.insert_attempt(attempt);
+    publish(&js);
+}
+/*
+    function: to hide the details of hidden cases from a JobResponse
+    input: job: a &JobResponse to be shown
+           viewer: an &Option<User> of the user asking, None without authentication
+           config: a &Config of the global config
+    output: a JobResponse whose hidden cases keep only their id and result, unless shown to staff
+*/
+fn redact(job: &JobResponse, viewer: &Option<User>, config: &Config) -> JobResponse {
+    let mut job = job.clone();
+    redact_cases(&mut job.cases, job.submission.problem_id, viewer, config);
+    job
+}
+/*
+    function: to check if the source code of a job may be shown to a user
+    input: viewer: an &Option<User> of the user asking, None without authentication
+           job: a &JobResponse to be shown
+    output: true if the job is the viewer's own or the viewer runs contests, false if not
+*/
+fn source_visible(viewer: &Option<User>, job: &JobResponse) -> bool {
+    match viewer {
+        Some(user) => user.id == job.submission.user_id || holds(user, STAFF),
+        None => true,
+    }
+}
+/*
+    function: to show a job to a user
+    input: job: a &JobResponse to be shown
+           viewer: an &Option<User> of the user asking
+           config: a &Config of the global config
+           version: the Version of the api asked
+    output: a Value of the job, redacted and without somebody else's source code
+*/
+fn job_value(
+    job: &JobResponse,
+    viewer: &Option<User>,
+    config: &Config,
+    version: Version,
+) -> serde_json::Value {
+    let text = serde_json::to_string(&version.body(redact(job, viewer, config))).unwrap();
+    let mut value: serde_json::Value = serde_json::from_str(&text).unwrap();
+    if !source_visible(viewer, job) {
+        value["submission"]
+            .as_object_mut()
+            .unwrap()
+            .remove("source_code");
+    }
+    value
+}
+/*
+    function: to turn the change of a job into server-sent events
+    input: job: a &JobResponse as it is now
+           last: an Option<&JobResponse> of the job as it was last sent, None for the first event
+           viewer: an &Option<User> of the user watching
+           config: a &Config of the global config
+           version: the Version of the api the stream was asked by
+    output: a String of a case event for every case changed, then a job event if its state, result or score changed
+*/
+fn job_events_text(
+    job: &JobResponse,
+    last: Option<&JobResponse>,
+    viewer: &Option<User>,
+    config: &Config,
+    version: Version,
+) -> String {
+    let value = job_value(job, viewer, config, version);
+    let mut text = String::new();
+    if let Some(last) = last {
+        let (now, before) = (redact(job, viewer, config), redact(last, viewer, config));
+        for (i, case) in now.cases.iter().enumerate() {
+            if before.cases.get(i) != Some(case) {
+                let case = serde_json::to_string(case).unwrap();
+                text += &format!("event: case\ndata: {}\n\n", case);
+            }
+        }
+        // the cases are sent one by one as they are judged
+        if job.state == last.state && job.result == last.result && job.score == last.score {
+            return text;
+        }
+    }
+    text + &format!("event: job\ndata: {}\n\n", value)
+}
+/*
+    function: to hide the details of hidden cases from the cases of a judging
+    input: cases: a &mut [Case] of a job or an attempt
+           pid: a usize of the problem judged
+           viewer: an &Option<User> of the user asking, None without authentication
+           config: a &Config of the global config
+*/
+fn redact_cases(cases: &mut [Case], pid: usize, viewer: &Option<User>, config: &Config) {
+    if matches!(viewer, Some(user) if holds(user, STAFF)) {
+        return;
+    } // the admins and problem setters see every case in full
+    if let Some(problem) = config.problems.iter().find(|problem| problem.id == pid) {
+        for (case, problem_case) in cases.iter_mut().skip(1).zip(&problem.cases) {
+            // cases[0] is the compilation, cases[i] stands for the problem's case i - 1
+            if problem_case.visibility == Some(Visibility::Hidden) {
+                case.time = 0;
+                case.memory = 0.0;
+                case.info = String::from("");
+            }
+        }
+    }
+}
 /*
     function: to post a JobRequest
     input: body: a web::Json<JobRequest> that bears the JobRequest
+           req: a HttpRequest bearing the token
     output: Responder
 */
 #[post("/jobs")]
-pub async fn post_job(body: web::Json<JobRequest>) -> impl Responder {
-    let req = body.into_inner();
+pub async fn post_job(
+    body: web::Json<JobRequest>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let viewer = authorize(&req, ANYONE)?;
+    let version = Version::of(&req);
+    let peer = req.peer_addr().map(|addr| addr.ip());
+    let forwarded = req
+        .headers()
+        .get("X-Forwarded-For")
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    let mut req = body.into_inner();
+    if let Some(user) = &viewer {
+        req.user_id = user.id;
+    }
+    // with authentication, the job belongs to the user logged in whatever the body claims
+    let user_id = match req.user_id {
+        Some(id) => id,
+        None => {
+            return Err(Error::invalid_argument(String::from(
+                "Invalid request body: missing field `user_id`.",
+            )))
+        }
+    }; // without, the body has to name the user
+    if SHUTDOWN.load(Ordering::SeqCst) {
+        return Err(
+            Error::invalid_state(String::from("Server is shutting down."))
+                .with_status(StatusCode::SERVICE_UNAVAILABLE),
+        );
+    }
     let config = &CONFIG.lock().unwrap();
+    let ip = client_ip(
+        peer,
+        forwarded.as_deref(),
+        config.server.trusted_proxies.as_deref().unwrap_or_default(),
+    );
+    // the client address, for the limits per ip
     let userlist = &USER_LIST.lock().unwrap();
     let ctlist = &CONTEST_LIST.lock().unwrap();
     let joblist = &mut JOB_LIST.lock().unwrap();
@@ -476,809 +1034,932 @@ pub async fn post_job(body: web::Json<JobRequest>) -> impl Responder {
     for lang in &config.languages {
         if lang.name == req.language {
             lcheck = true;
+            source_check(&req.source_code, lang).map_err(Error::invalid_argument)?;
             break;
         }
-    } // check if the language is in the config
+    } // check if the language is in the config, and the source code fits it
     for pbm in &config.problems {
         if pbm.id == req.problem_id {
             pcheck = true;
             break;
         }
     } // check if the problem id is in the config
-    for uindex in 0..userlist.len() {
-        if userlist[uindex].id.unwrap() == req.user_id {
-            ucheck = true;
-            break;
-        }
+    if userlist.contains(user_id) {
+        ucheck = true;
     } // check if the user id is in the userlist
     let mut cexist = false;
     let mut cqulified = false;
     let mut submit = false;
-    for cindex in 0..ctlist.len() {
-        if ctlist[cindex].id.unwrap() == req.contest_id {
-            cexist = true;
-            // check if the language is in the ctlist
-            let ct = ctlist[cindex].clone();
-            if later(&created_time, &ct.from)
-                && later(&ct.to, &created_time)
-                && ct.user_ids.contains(&req.user_id)
-                && ct.problem_ids.contains(&req.problem_id)
-            {
-                // check if the JobRequest fits the contest's requirement
-                cqulified = true;
-                let mut count = 0;
-                for i in 0..joblist.len() {
-                    if joblist[i].submission.user_id == req.user_id
-                        && joblist[i].submission.problem_id == req.problem_id
-                        && joblist[i].submission.contest_id == req.contest_id
-                    {
-                        count += 1;
-                    }
-                }
-                // count the times of submission of this contest&&user&&problem in before
-                if count < ct.submission_limit {
-                    submit = true;
-                    ccheck = true; // the overall check related to contest
-                }
+    if let Some(ct) = ctlist.get(req.contest_id) {
+        cexist = true;
+        // check if the contest is in the ctlist
+        if later(&created_time, &ct.from)
+            && later(&ct.to, &created_time)
+            && ct.user_ids.contains(&user_id)
+            && ct.problem_ids.contains(&req.problem_id)
+        {
+            // check if the JobRequest fits the contest's requirement
+            cqulified = true;
+            let count = joblist
+                .by_user(user_id)
+                .filter(|job| {
+                    job.submission.problem_id == req.problem_id
+                        && job.submission.contest_id == req.contest_id
+                        && job.state != State::Canceled
+                })
+                .count();
+            // count the times of submission of this contest&&user&&problem in before
+            if count < ct.submission_limit {
+                submit = true;
+                ccheck = true; // the overall check related to contest
             }
-            break;
         }
     }
     if lcheck && pcheck && ucheck && ccheck {
         // all fits
-        let mut js = process_post(&req, &config, joblist.len()).await;
-        let conn = &mut MYSQL.lock().unwrap();
-        if conn.is_ok() {
-            let mut conn = conn.as_ref().unwrap().get_conn().unwrap();
-            let stmt = conn
-                .prep(
-                    "
-                INSERT INTO job_submit (id, source_code, language, user_id, contest_id, problem_id) 
-                values(?, ?, ?, ?, ?, ?)",
-                )
-                .unwrap();
-            conn.exec_iter(
-                stmt,
-                (
-                    js.id,
-                    js.submission.source_code.clone(),
-                    js.submission.language.clone(),
-                    js.submission.user_id,
-                    js.submission.contest_id,
-                    js.submission.problem_id,
-                ),
-            )
-            .unwrap();
-            // store job_submit
-            for i in 0..js.cases.len() {
-                let stmt = conn
-                    .prep(
-                        "
-                    INSERT INTO job_cases (jobid, caseid, result, time, memory, info) 
-                    values(?, ?, ?, ?, ?, ?)",
-                    )
-                    .unwrap();
-                conn.exec_iter(
-                    stmt,
-                    (
-                        js.id,
-                        i,
-                        js.cases[i].result.clone().to_string(),
-                        js.cases[i].time,
-                        js.cases[i].memory,
-                        js.cases[i].info.clone(),
-                    ),
-                )
-                .unwrap();
-            }
-            // store job_cases
-            let stmt = conn
-                .prep(
-                    "
-                INSERT INTO joblist (id, create_time, update_time, state, result, score) 
-                values(?, ?, ?, ?, ?, ?)",
-                )
-                .unwrap();
-            conn.exec_iter(
-                stmt,
-                (
-                    js.id,
-                    js.created_time.clone(),
-                    js.updated_time.clone(),
-                    js.state.to_string(),
-                    js.result.to_string(),
-                    js.score,
-                ),
-            )
-            .unwrap();
-            // store job itself
+        let mut limits = vec![];
+        if let Some(limit) = &config.rate_limit {
+            limits.push((None, limit));
+        }
+        if let Some(limit) = ctlist
+            .get(req.contest_id)
+            .and_then(|ct| ct.rate_limit.as_ref())
+        {
+            limits.push((Some(req.contest_id), limit));
+        } // the global limit and that of the contest both apply
+        if let Err(secs) = RATE_LIMITER.lock().unwrap().take(&limits, user_id, ip) {
+            return Err(Error::rate_limit(format!(
+                "Too many submissions, retry after {} seconds.",
+                secs
+            ))
+            .retry_after(secs));
+        }
+        let id = joblist.next_id();
+        let mut js = queueing_job(&req, config, id, created_time);
+        if let Some(storage) = STORAGE.lock().unwrap().as_mut() {
+            if let Err(err) = storage.insert_job(&js) {
+                RATE_LIMITER.lock().unwrap().refund(&limits, user_id, ip);
+                return Err(storage_failure(err));
+            } // a submission not stored does not count
+              // store the job in storage
         } else {
-            js.warning = Some("fail to connect to mysql".to_string());
+            js.warning = Some(NOT_PERSISTED.to_string());
         }
-        joblist.push(js.clone());
-        HttpResponse::Ok().json(js)
-    } else if !lcheck || !pcheck || !ucheck || !cexist {
-        // language or problem id or user id or contest id is not in the config
-        HttpResponse::NotFound().json(Error {
-            code: 3,
-            reason: String::from("ERR_NOT_FOUND"),
-            message: String::from("Not Found"),
-        })
+        joblist.insert(js.clone());
+        enqueue(id);
+        Ok(HttpResponse::Ok().json(version.body(redact(&js, &viewer, config))))
+    } else if !lcheck {
+        Err(Error::not_found(format!(
+            "Language {} not found.",
+            req.language
+        )))
+    } else if !pcheck {
+        Err(Error::not_found(format!(
+            "Problem {} not found.",
+            req.problem_id
+        )))
+    } else if !ucheck {
+        Err(Error::not_found(format!("User {} not found.", user_id)))
+    } else if !cexist {
+        Err(Error::not_found(format!(
+            "Contest {} not found.",
+            req.contest_id
+        )))
     } else if !cqulified {
         // not registered in the corresponding contest
-        HttpResponse::BadRequest().json(Error {
-            code: 1,
-            reason: String::from("ERR_INVALID_ARGUMENT"),
-            message: String::from("Bad Request"),
-        })
+        let ct = ctlist.get(req.contest_id).unwrap();
+        Err(Error::invalid_argument(
+            if !later(&created_time, &ct.from) {
+                format!("Contest {} has not started.", req.contest_id)
+            } else if !later(&ct.to, &created_time) {
+                format!("Contest {} has ended.", req.contest_id)
+            } else if !ct.user_ids.contains(&user_id) {
+                format!("User {} is not in contest {}.", user_id, req.contest_id)
+            } else {
+                format!(
+                    "Problem {} is not in contest {}.",
+                    req.problem_id, req.contest_id
+                )
+            },
+        ))
     } else if !submit {
-        // exceed submit limit
-        HttpResponse::BadRequest().json(Error {
-            code: 4,
-            reason: String::from("ERR_RATE_LIMIT"),
-            message: String::from("Bad Request"),
-        })
+        Err(Error::rate_limit(format!(
+            "User {} has used up the {} submissions to problem {} in contest {}.",
+            user_id,
+            ctlist.get(req.contest_id).unwrap().submission_limit,
+            req.problem_id,
+            req.contest_id
+        )))
     } else {
         // unexpected err
-        HttpResponse::InternalServerError().json(Error {
-            code: 6,
-            reason: String::from("ERR_INTERNAL"),
-            message: String::from("Internal Server Error"),
-        })
+        Err(Error::internal(String::from("Internal Server Error")))
+    }
+}
+/*
+    function: to check an argument of a query
+    input: name: a &str of the argument
+           arg: an &Option<String> of its value
+           valid: a closure telling if the value can be parsed
+    output: Ok(()) if the argument is absent or valid, otherwise an Error naming it
+*/
+fn check_arg(name: &str, arg: &Option<String>, valid: impl Fn(&str) -> bool) -> Result<(), Error> {
+    match arg {
+        Some(value) if !valid(value) => Err(Error::invalid_argument(format!(
+            "Invalid argument {}: '{}'.",
+            name, value
+        ))),
+        _ => Ok(()),
     }
 }
+/*
+    function: to check the filters in JobArgs
+    input: args: a &JobArgs of the filters
+    output: Ok(()) if every filter listed can be parsed, otherwise an Error naming the first that cannot
+*/
+fn check_filters(args: &JobArgs) -> Result<(), Error> {
+    let ids = |ids: &str| string2ids(ids).is_ok();
+    let time = |t: &str| NaiveDateTime::parse_from_str(t, "%Y-%m-%dT%H:%M:%S%.3fZ").is_ok();
+    let score = |s: &str| s.parse::<f64>().is_ok();
+    check_arg("contest_id", &args.contest_id, ids)?;
+    check_arg("problem_id", &args.problem_id, ids)?;
+    check_arg("user_id", &args.user_id, ids)?;
+    check_arg("from", &args.from, time)?;
+    check_arg("to", &args.to, time)?;
+    check_arg("updated_from", &args.updated_from, time)?;
+    check_arg("updated_to", &args.updated_to, time)?;
+    check_arg("score_from", &args.score_from, score)?;
+    check_arg("score_to", &args.score_to, score)?;
+    check_arg("state", &args.state, |state| string2state(state).is_ok())?;
+    check_arg("result", &args.result, |result| {
+        string2results(result).is_ok()
+    })
+}
+/*
+    function: to find the jobs matching the filters in JobArgs
+    input: args: a &JobArgs of the filters, already checked by check_filters
+           joblist: a &JobStore of all jobs
+    output: a Vec of the matching jobs in the order of their ids
+*/
+fn filter_jobs<'a>(args: &JobArgs, joblist: &'a JobStore) -> Vec<&'a JobResponse> {
+    let list = |ids: &Option<String>| ids.as_ref().map(|ids| string2ids(ids).unwrap());
+    let mut jobs: Vec<&JobResponse> = if let Some(uids) = list(&args.user_id) {
+        uids.into_iter()
+            .flat_map(|uid| joblist.by_user(uid))
+            .collect()
+    } else if let Some(pids) = list(&args.problem_id) {
+        pids.into_iter()
+            .flat_map(|pid| joblist.by_problem(pid))
+            .collect()
+    } else if let Some(cids) = list(&args.contest_id) {
+        cids.into_iter()
+            .flat_map(|cid| joblist.by_contest(cid))
+            .collect()
+    } else {
+        joblist.iter().collect()
+    };
+    jobs.sort_by_key(|job| job.id);
+    jobs.dedup_by_key(|job| job.id);
+    // narrow down with an index when possible, the ids listed may repeat
+    jobs.retain(|job| job.jobcheck(args));
+    // then check the rest of the args
+    jobs
+}
 /*
     function: to get the JobResponses according to args
     input: args: a web::Query<JobArgs> that bears the args
-    output: Responder
+           req: a HttpRequest bearing the token
+    output: Responder, a page of the jobs with the total count in X-Total-Count,
+            and the cursor for the next page in X-Next-Cursor if there is one
 */
 #[get("/jobs")]
-pub async fn get_jobs(args: web::Query<JobArgs>) -> impl Responder {
-    if (args.contest_id.is_some() && args.contest_id.as_ref().unwrap().parse::<usize>().is_err())
-        || (args.problem_id.is_some()
-            && args.problem_id.as_ref().unwrap().parse::<usize>().is_err())
-        || (args.user_id.is_some() && args.user_id.as_ref().unwrap().parse::<usize>().is_err())
-        || (args.from.is_some()
-            && NaiveDateTime::parse_from_str(
-                &args.from.as_ref().unwrap(),
-                "%Y-%m-%dT%H:%M:%S%.3fZ",
-            )
-            .is_err())
-        || (args.to.is_some()
-            && NaiveDateTime::parse_from_str(&args.to.as_ref().unwrap(), "%Y-%m-%dT%H:%M:%S%.3fZ")
-                .is_err())
-        || (args.state.is_some() && string2state(args.state.as_ref().unwrap()).is_err())
-        || (args.result.is_some() && string2result(args.result.as_ref().unwrap()).is_err())
-    {
-        // invalid args
-        HttpResponse::NotFound().json(Error {
-            code: 1,
-            reason: String::from("ERR_INVALID_ARGUMENT"),
-            message: String::from("Invalid argument XXX"),
-        })
-    } else {
-        let mut jobs = vec![];
-        let joblist = &JOB_LIST.lock().unwrap();
-        for i in 0..joblist.len() {
-            if joblist[i].jobcheck(&args) {
-                jobs.push(joblist[i].clone());
+pub async fn get_jobs(args: web::Query<JobArgs>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    let viewer = authorize(&req, ANYONE)?;
+    let number = |n: &str| n.parse::<usize>().is_ok();
+    check_filters(&args)?;
+    check_arg("limit", &args.limit, number)?;
+    check_arg("offset", &args.offset, number)?;
+    check_arg("cursor", &args.cursor, number)?;
+    check_arg("order", &args.order, |order| {
+        order == "asc" || order == "desc"
+    })?;
+    check_arg("omit", &args.omit, |omit| {
+        omit.split(',')
+            .all(|field| field == "source_code" || field == "cases")
+    })?;
+    let version = Version::of(&req);
+    let config = &CONFIG.lock().unwrap();
+    let joblist = &JOB_LIST.lock().unwrap();
+    let parse = |id: &Option<String>| id.as_ref().map(|id| id.parse::<usize>().unwrap());
+    let mut jobs = filter_jobs(&args, joblist);
+    let total = jobs.len();
+    let desc = args.order.as_deref() == Some("desc");
+    if desc {
+        jobs.reverse();
+    } // the jobs come in the order of their ids
+    if let Some(cursor) = parse(&args.cursor) {
+        jobs.retain(|job| {
+            if desc {
+                job.id < cursor
+            } else {
+                job.id > cursor
+            }
+        });
+    } // the cursor is the id of the last job on the previous page
+    let offset = parse(&args.offset).unwrap_or(0);
+    let limit = parse(&args.limit).unwrap_or(usize::MAX);
+    let page: Vec<&JobResponse> = jobs.iter().skip(offset).take(limit).copied().collect();
+    let omit: Vec<&str> = args
+        .omit
+        .as_deref()
+        .map_or(vec![], |omit| omit.split(',').collect());
+    let body: Vec<serde_json::Value> = page
+        .iter()
+        .map(|job| {
+            let hide_source = omit.contains(&"source_code") || !source_visible(&viewer, job);
+            let text = serde_json::to_string(&version.body(redact(job, &viewer, config))).unwrap();
+            let mut job: serde_json::Value = serde_json::from_str(&text).unwrap();
+            // through a string, since a Value cannot hold the u128 times of the cases
+            if hide_source {
+                job["submission"]
+                    .as_object_mut()
+                    .unwrap()
+                    .remove("source_code");
             }
+            if omit.contains(&"cases") {
+                job.as_object_mut().unwrap().remove("cases");
+            }
+            job
+        })
+        .collect();
+    let mut response = HttpResponse::Ok();
+    response.insert_header(("X-Total-Count", total.to_string()));
+    if offset + page.len() < jobs.len() {
+        if let Some(last) = page.last() {
+            response.insert_header(("X-Next-Cursor", last.id.to_string()));
         }
-        HttpResponse::Ok().json(jobs)
-    }
+    } // more jobs are left after this page
+    Ok(response.json(body))
 }
 /*
     function: to get the JobResponses according to the jobid
     input: jobid: a web::Path<String> that bears the job id
+           req: a HttpRequest bearing the token
     output: Responder
 */
 #[get("/jobs/{jobid}")]
-pub async fn job_id(jobid: web::Path<String>) -> impl Responder {
-    let jobid = jobid.parse::<usize>();
-    if jobid.is_ok() {
+pub async fn job_id(jobid: web::Path<String>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    let viewer = authorize(&req, ANYONE)?;
+    if let Ok(id) = jobid.parse::<usize>() {
         // if the jobid in the path is a number
+        let config = &CONFIG.lock().unwrap();
         let joblist = &JOB_LIST.lock().unwrap();
-        let mut index: Option<usize> = None;
-        for i in 0..joblist.len() {
-            if joblist[i].id == jobid.clone().unwrap() {
-                index = Some(i);
-                break;
-            }
-        }
-        if index.is_some() {
+        if let Some(job) = joblist.get(id) {
             // found the job
-            return HttpResponse::Ok().json(joblist[index.unwrap()].clone());
+            return Ok(HttpResponse::Ok().json(job_value(job, &viewer, config, Version::of(&req))));
+            // somebody else's source code is left out
         }
     }
-    HttpResponse::NotFound().json(Error {
-        code: 3,
-        reason: String::from("ERR_NOT_FOUND"),
-        message: String::from("Job 123456 not found."),
-    })
+    Err(Error::not_found(format!("Job {} not found.", jobid)))
+}
+/*
+    function: to follow a job as server-sent events until it is finished or canceled
+    input: jobid: a web::Path<String> that bears the job id
+           req: a HttpRequest bearing the token
+    output: Responder, a text/event-stream of the job as it is first,
+            then a case event for each case judged and a job event for each change of state
+*/
+#[get("/jobs/{jobid}/events")]
+pub async fn job_events(jobid: web::Path<String>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    let viewer = authorize(&req, ANYONE)?;
+    let version = Version::of(&req);
+    if let Ok(id) = jobid.parse::<usize>() {
+        let joblist = &JOB_LIST.lock().unwrap();
+        if let Some(job) = joblist.get(id) {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            let _ = sender.send(job.clone());
+            if job.state != State::Finished && job.state != State::Canceled {
+                WATCHERS.lock().unwrap().entry(id).or_default().push(sender);
+            } // a job settled is sent once, and the stream ends
+            let events = stream::unfold(
+                (receiver, None, viewer),
+                move |(mut receiver, mut last, viewer): (_, Option<JobResponse>, _)| async move {
+                    while let Some(job) = receiver.recv().await {
+                        let text = {
+                            let config = &CONFIG.lock().unwrap();
+                            job_events_text(&job, last.as_ref(), &viewer, config, version)
+                        };
+                        last = Some(job);
+                        if !text.is_empty() {
+                            let bytes = web::Bytes::from(text);
+                            return Some((Ok::<_, Infallible>(bytes), (receiver, last, viewer)));
+                        }
+                    }
+                    None
+                },
+            );
+            return Ok(HttpResponse::Ok()
+                .content_type("text/event-stream")
+                .insert_header((header::CACHE_CONTROL, "no-cache"))
+                .streaming(events));
+        }
+    }
+    Err(Error::not_found(format!("Job {} not found.", jobid)))
+}
+/*
+    function: to get every judging of a job, the latest one last
+    input: jobid: a web::Path<String> that bears the job id
+           req: a HttpRequest bearing the token
+    output: Responder
+*/
+#[get("/jobs/{jobid}/attempts")]
+pub async fn job_attempts(
+    jobid: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let viewer = authorize(&req, ANYONE)?;
+    if let Ok(id) = jobid.parse::<usize>() {
+        let config = &CONFIG.lock().unwrap();
+        let joblist = &JOB_LIST.lock().unwrap();
+        if let Some(job) = joblist.get(id) {
+            let attempts: Vec<Attempt> = joblist
+                .attempts(id)
+                .iter()
+                .map(|attempt| {
+                    let mut attempt = attempt.clone();
+                    redact_cases(
+                        &mut attempt.cases,
+                        job.submission.problem_id,
+                        &viewer,
+                        config,
+                    );
+                    attempt
+                })
+                .collect();
+            return Ok(HttpResponse::Ok().json(attempts));
+        }
+    }
+    Err(Error::not_found(format!("Job {} not found.", jobid)))
 }
 /*
     function: to retest JobResponses according to the jobid
     input: jobid: a web::Path<String> that bears the job id
+           req: a HttpRequest bearing the token
     output: Responder
 */
 #[put("/jobs/{jobid}")]
-pub async fn put_job(jobid: web::Path<String>) -> impl Responder {
-    let jobid = jobid.parse::<usize>();
-    if jobid.is_ok() {
-        let id = jobid.unwrap();
-        let joblist = &mut JOB_LIST.lock().unwrap();
+pub async fn put_job(jobid: web::Path<String>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    let viewer = authorize(&req, STAFF)?;
+    if let Ok(id) = jobid.parse::<usize>() {
         let config = &CONFIG.lock().unwrap();
-        let mut index: Option<usize> = None;
-        for i in 0..joblist.len() {
-            if joblist[i].id == id {
-                index = Some(i);
+        let joblist = &mut JOB_LIST.lock().unwrap();
+        if let Some(job) = joblist.get(id) {
+            // found the job
+            if job.state == State::Queueing || job.state == State::Running {
+                // still waiting for its judgement
+                return Err(Error::invalid_state(format!("Job {} not finished.", id)));
+            }
+            let mut js = queueing_job(&job.submission, config, id, job.created_time.clone());
+            // back to the queue, keeping the created time
+            if let Some(storage) = STORAGE.lock().unwrap().as_mut() {
+                storage.update_job(&js).map_err(storage_failure)?;
+                // replace the cases and the result in storage
+            } else {
+                js.warning = Some(NOT_PERSISTED.to_string());
+            }
+            joblist.insert(js.clone());
+            enqueue(id);
+            return Ok(
+                HttpResponse::Ok().json(Version::of(&req).body(redact(&js, &viewer, config)))
+            );
+        }
+    }
+    Err(Error::not_found(format!("Job {} not found.", jobid)))
+}
+/*
+    function: to rejudge all finished jobs matching the filters in JobArgs
+    input: args: a web::Query<JobArgs> that bears the filters, as in getting jobs
+           req: a HttpRequest bearing the token
+    output: Responder, the Rejudge batch with the previous verdict of every job queued
+*/
+#[post("/rejudges")]
+pub async fn post_rejudge(
+    args: web::Query<JobArgs>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    authorize(&req, STAFF)?;
+    check_filters(&args)?;
+    let config = &CONFIG.lock().unwrap();
+    let joblist = &mut JOB_LIST.lock().unwrap();
+    let rejudges = &mut REJUDGE_LIST.lock().unwrap();
+    let mut rejudge = Rejudge {
+        id: rejudges.len(),
+        created_time: Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+        total: 0,
+        finished: 0,
+        jobs: vec![],
+        skipped: vec![],
+    };
+    let matched: Vec<JobResponse> = filter_jobs(&args, joblist).into_iter().cloned().collect();
+    let mut failure = None;
+    for job in matched {
+        if job.state == State::Queueing || job.state == State::Running {
+            rejudge.skipped.push(job.id);
+            continue;
+        } // already waiting for its judgement
+        let js = queueing_job(&job.submission, config, job.id, job.created_time.clone());
+        if let Some(storage) = STORAGE.lock().unwrap().as_mut() {
+            if let Err(err) = storage.update_job(&js) {
+                failure = Some(err);
                 break;
             }
         }
-        if index.is_some() {
-            // found the job
-            let index = index.unwrap();
-            let before = joblist[index].created_time.clone();
-            let req = joblist[index].submission.clone();
-            let mut js = process_post(&req, &config, index).await;
-            // retest
-            js.created_time = before;
-            // keep the created time
-            let conn = &mut MYSQL.lock().unwrap();
-            if conn.is_ok() {
-                let mut conn = conn.as_ref().unwrap().get_conn().unwrap();
-                let stmt = conn.prep("delete from job_cases where jobid = ? ").unwrap();
-                conn.exec_iter(stmt, (id,)).unwrap();
-                // delete the origin job_cases in mysql
-                for i in 0..js.cases.len() {
-                    let stmt = conn
-                        .prep(
-                            "
-                        INSERT INTO job_cases (jobid, caseid, result, time, memory, info) 
-                        values(?, ?, ?, ?, ?, ?)",
-                        )
-                        .unwrap();
-                    conn.exec_iter(
-                        stmt,
-                        (
-                            js.id,
-                            i,
-                            js.cases[i].result.clone().to_string(),
-                            js.cases[i].time,
-                            js.cases[i].memory,
-                            js.cases[i].info.clone(),
-                        ),
-                    )
-                    .unwrap();
+        rejudge.jobs.push(RejudgedJob {
+            id: job.id,
+            previous_result: job.result,
+            previous_score: job.score,
+            state: js.state.clone(),
+            result: js.result.clone(),
+            score: js.score,
+        });
+        joblist.insert(js);
+        enqueue(job.id);
+    }
+    // the jobs queued before a failed write are still rejudged, and kept in the batch
+    rejudge.total = rejudge.jobs.len();
+    rejudges.push(rejudge.clone());
+    match failure {
+        Some(err) => Err(storage_failure(err)),
+        None => Ok(HttpResponse::Ok().json(rejudge)),
+    }
+}
+/*
+    function: to get the progress of a rejudge
+    input: rid: a web::Path<String> that bears the rejudge id
+           req: a HttpRequest bearing the token
+    output: Responder, the Rejudge batch with the current verdict of every job
+*/
+#[get("/rejudges/{rid}")]
+pub async fn rejudge_id(rid: web::Path<String>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    authorize(&req, ANYONE)?;
+    if let Ok(id) = rid.parse::<usize>() {
+        let joblist = &JOB_LIST.lock().unwrap();
+        let rejudges = &mut REJUDGE_LIST.lock().unwrap();
+        if let Some(rejudge) = rejudges.get_mut(id) {
+            rejudge.progress(joblist);
+            return Ok(HttpResponse::Ok().json(rejudge.clone()));
+        }
+    }
+    Err(Error::not_found(format!("Rejudge {} not found.", rid)))
+}
+/*
+    function: to get the jobs whose verdict changed in a rejudge
+    input: rid: a web::Path<String> that bears the rejudge id
+           req: a HttpRequest bearing the token
+    output: Responder, the finished jobs of the rejudge with a different result or score
+*/
+#[get("/rejudges/{rid}/changes")]
+pub async fn rejudge_changes(
+    rid: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    authorize(&req, ANYONE)?;
+    if let Ok(id) = rid.parse::<usize>() {
+        let joblist = &JOB_LIST.lock().unwrap();
+        let rejudges = &mut REJUDGE_LIST.lock().unwrap();
+        if let Some(rejudge) = rejudges.get_mut(id) {
+            rejudge.progress(joblist);
+            let changes: Vec<&RejudgedJob> = rejudge
+                .jobs
+                .iter()
+                .filter(|job| {
+                    job.state == State::Finished
+                        && (job.result != job.previous_result || job.score != job.previous_score)
+                })
+                .collect();
+            return Ok(HttpResponse::Ok().json(changes));
+        }
+    }
+    Err(Error::not_found(format!("Rejudge {} not found.", rid)))
+}
+/*
+    function: to cancel a job waiting in the queue or being judged
+    input: jobid: a web::Path<String> that bears the job id
+           req: a HttpRequest bearing the token
+    output: Responder, the job Canceled, whose cases not judged yet are Skipped
+*/
+#[post("/jobs/{jobid}/cancel")]
+pub async fn cancel_job(jobid: web::Path<String>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    let viewer = authorize(&req, ANYONE)?;
+    if let Ok(id) = jobid.parse::<usize>() {
+        let config = &CONFIG.lock().unwrap();
+        let joblist = &mut JOB_LIST.lock().unwrap();
+        if let Some(job) = joblist.get(id) {
+            if let Some(user) = &viewer {
+                if user.id != job.submission.user_id && !holds(user, STAFF) {
+                    return Err(Error::forbidden(format!(
+                        "User {} may only cancel their own jobs.",
+                        user.name
+                    )));
                 }
-                // insert with new job_cases
-                let stmt = conn.prep("delete from joblist where id = ? ").unwrap();
-                conn.exec_iter(stmt, (id,)).unwrap();
-                let stmt = conn
-                    .prep(
-                        "
-                    INSERT INTO joblist (id, create_time, update_time, state, result, score) 
-                    values(?, ?, ?, ?, ?, ?)",
-                    )
-                    .unwrap();
-                // delete the origin job in joblist in mysql
-                conn.exec_iter(
-                    stmt,
-                    (
-                        js.id,
-                        js.created_time.clone(),
-                        js.updated_time.clone(),
-                        js.state.to_string(),
-                        js.result.to_string(),
-                        js.score,
-                    ),
-                )
-                .unwrap();
-                // insert with new job
-            } else {
-                js.warning = Some("fail to connect to mysql".to_string());
             }
-            joblist[index] = js.clone();
-            return HttpResponse::Ok().json(js.clone());
+            if job.state != State::Queueing && job.state != State::Running {
+                return Err(Error::invalid_state(format!(
+                    "Job {} not queueing or running.",
+                    id
+                )));
+            }
+            let mut js = job.clone();
+            js.state = State::Canceled;
+            js.result = MyResult::Skipped;
+            for case in js.cases.iter_mut() {
+                if case.result == MyResult::Waiting || case.result == MyResult::Running {
+                    case.result = MyResult::Skipped;
+                }
+            }
+            js.updated_time = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+            if let Some(storage) = STORAGE.lock().unwrap().as_mut() {
+                storage.update_job(&js).map_err(storage_failure)?;
+            }
+            joblist.insert(js.clone());
+            publish(&js);
+            // a queueing job is passed over by the judge, a running one has its programme killed
+            return Ok(
+                HttpResponse::Ok().json(Version::of(&req).body(redact(&js, &viewer, config)))
+            );
         }
     }
-    HttpResponse::NotFound().json(Error {
-        code: 3,
-        reason: String::from("ERR_NOT_FOUND"),
-        message: String::from("Job 123456 not found."),
-    })
+    Err(Error::not_found(format!("Job {} not found.", jobid)))
 }
 /*
     function: to delete JobResponses according to the jobid
     input: jobid: a web::Path<String> that bears the job id
+           req: a HttpRequest bearing the token
     output: Responder
 */
 #[delete("/jobs/{jobid}")]
-pub async fn delete_job(jobid: web::Path<String>) -> impl Responder {
-    let jobid = jobid.parse::<usize>();
-    if jobid.is_ok() {
-        let id = jobid.unwrap();
+pub async fn delete_job(jobid: web::Path<String>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    authorize(&req, STAFF)?;
+    if let Ok(id) = jobid.parse::<usize>() {
         let joblist = &mut JOB_LIST.lock().unwrap();
-        let mut index: Option<usize> = None;
-        for i in 0..joblist.len() {
-            if joblist[i].id == id {
-                index = Some(i);
-                break;
-            }
-        }
-        if index.is_some() {
+        if let Some(job) = joblist.get(id) {
             // found the job
-            let index = index.unwrap();
-            if joblist[index].state == State::Queueing {
-                joblist.remove(index);
+            if job.state == State::Queueing {
+                let persisted = match STORAGE.lock().unwrap().as_mut() {
+                    Some(storage) => {
+                        storage.delete_job(id).map_err(storage_failure)?;
+                        true
+                    }
+                    None => false,
+                };
+                // delete the job in storage first
+                joblist.remove(id);
+                WATCHERS.lock().unwrap().remove(&id);
+                // and its event streams end
                 // delete the job in joblist
-                let conn = &mut MYSQL.lock().unwrap();
-                if conn.is_ok() {
-                    let mut conn = conn.as_ref().unwrap().get_conn().unwrap();
-                    let stmt = conn.prep("delete from joblist where id = ? ").unwrap();
-                    conn.exec_iter(stmt, (id,)).unwrap();
-                    let stmt = conn.prep("delete from job_submit where id = ? ").unwrap();
-                    conn.exec_iter(stmt, (id,)).unwrap();
-                    let stmt = conn.prep("delete from job_cases where jobid = ? ").unwrap();
-                    conn.exec_iter(stmt, (id,)).unwrap();
-                    // delete the job in mysql
-                    return HttpResponse::Ok().json({});
+                if persisted {
+                    return Ok(HttpResponse::Ok().json({}));
                 } else {
-                    let warning = "fail to connect to mysql".to_string();
-                    return HttpResponse::Ok().json(warning);
+                    return Ok(HttpResponse::Ok().json(NOT_PERSISTED));
                 }
             } else {
                 // not queueing
-                return HttpResponse::BadRequest().json(Error {
-                    code: 2,
-                    reason: String::from("ERR_INVALID_STATE"),
-                    message: String::from("Job 123456 not queuing."),
-                });
+                return Err(Error::invalid_state(format!("Job {} not queueing.", id)));
             }
         }
     }
-    HttpResponse::NotFound().json(Error {
-        code: 3,
-        reason: String::from("ERR_NOT_FOUND"),
-        message: String::from("Job 123456 not found."),
-    })
+    Err(Error::not_found(format!("Job {} not found.", jobid)))
 }
 /*
     function: to post a user
     input: body: a web::Json<User> that bears the user
+           req: a HttpRequest bearing the token
     output: Responder
 */
 #[post("/users")]
-pub async fn post_user(body: web::Json<User>) -> impl Responder {
+pub async fn post_user(body: web::Json<User>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    let viewer = authorize(&req, ANYONE)?;
     let userlist = &mut USER_LIST.lock().unwrap();
     let mut user = body.into_inner();
+    let password = user.password.take();
+    if password.as_deref() == Some("") {
+        return Err(Error::invalid_argument(String::from(
+            "Password must not be empty.",
+        )));
+    }
+    // the password is only kept as a salted hash
+    if let Some(viewer) = &viewer {
+        let other_role = matches!(user.role, Some(role) if Some(role) != viewer.role);
+        if !holds(viewer, ADMIN) && (user.id != viewer.id || other_role) {
+            return Err(Error::forbidden(format!(
+                "User {} may only change their own name and password.",
+                viewer.name
+            )));
+        }
+    }
+    // only the admins create users and give roles
     match user.id {
         Some(id) => {
-            let mut unique_id = true;
-            let mut unique_name = true;
-            let mut index = 0;
-            for i in 0..userlist.len() {
-                if userlist[i].id.unwrap() == id {
-                    unique_id = false;
-                    for j in 0..userlist.len() {
-                        if j != i && userlist[j].name == user.name {
-                            unique_name = false;
-                            break;
-                        }
-                    }
-                    index = i;
-                    break;
-                }
-            }
-            if !unique_id {
+            if userlist.contains(id) {
                 // id exists
+                user.role = user.role.or(userlist.get(id).unwrap().role);
+                if id == 0 && user.role != Some(Role::Admin) {
+                    return Err(Error::invalid_argument(String::from(
+                        "User 0 root must stay an admin.",
+                    )));
+                }
+                // the role is kept unless a new one is given
+                let unique_name = match userlist.id_of(&user.name) {
+                    Some(other) => other == id,
+                    None => true,
+                };
+                // the name is taken by nobody else
                 if unique_name {
                     // name not duplicate, update
-                    let conn = &mut MYSQL.lock().unwrap();
-                    if conn.is_ok() {
-                        let mut conn = conn.as_ref().unwrap().get_conn().unwrap();
-                        let stmt = conn
-                            .prep(
-                                "UPDATE userlist
-                            SET name = ?
-                            where id = ?",
-                            )
-                            .unwrap();
-                        conn.exec_iter(stmt, (user.name.clone(), id)).unwrap();
-                        // update the user in mysql
+                    let credential = password.map(|password| Credential::new(id, &password));
+                    if let Some(storage) = STORAGE.lock().unwrap().as_mut() {
+                        storage
+                            .update_user(&user, credential.as_ref())
+                            .map_err(storage_failure)?;
+                        // update the user in storage
                     } else {
-                        user.waring = Some("fail to connect to mysql".to_string());
+                        user.waring = Some(NOT_PERSISTED.to_string());
+                    }
+                    if let Some(credential) = credential {
+                        CREDENTIALS.lock().unwrap().insert(id, credential);
                     }
-                    userlist[index] = user.clone();
-                    HttpResponse::Ok().json(user)
+                    userlist.insert(user.clone());
+                    Ok(HttpResponse::Ok().json(Version::of(&req).body(user)))
                 } else {
                     // name duplicate
-                    HttpResponse::BadRequest().json(Error {
-                        code: 1,
-                        reason: String::from("ERR_INVALID_ARGUMENT"),
-                        message: String::from("User name 'root' already exists."),
-                    })
+                    Err(Error::invalid_argument(format!(
+                        "User name '{}' already exists.",
+                        user.name
+                    )))
                 }
             } else {
                 // id not exist
-                HttpResponse::NotFound().json(Error {
-                    code: 3,
-                    reason: String::from("ERR_NOT_FOUND"),
-                    message: String::from("User 123456 not found."),
-                })
+                Err(Error::not_found(format!("User {} not found.", id)))
             }
         }
         None => {
-            let mut unique_name = true;
-            for i in 0..userlist.len() {
-                if userlist[i].name == user.name {
-                    unique_name = false;
-                    break;
-                }
-            }
-            if unique_name {
+            if userlist.id_of(&user.name).is_none() {
                 // no id, name not duplicate, new user
-                let mut max = userlist[0].id.unwrap();
-                for i in 0..userlist.len() {
-                    if userlist[i].id.unwrap() > max {
-                        max = userlist[i].id.unwrap();
-                    }
-                }
+                let id = userlist.next_id();
                 let mut newuser = User {
-                    id: Some(max + 1),
+                    id: Some(id),
                     name: user.name,
+                    password: None,
+                    role: user.role.or(Some(Role::Contestant)),
                     waring: None,
                 };
+                let credential = password.map(|password| Credential::new(id, &password));
                 let ctlist = &mut CONTEST_LIST.lock().unwrap();
-                ctlist[0].user_ids.push(max + 1);
-                let conn = &mut MYSQL.lock().unwrap();
-                if conn.is_ok() {
-                    let mut conn = conn.as_ref().unwrap().get_conn().unwrap();
-                    let stmt = conn
-                        .prep(
-                            "
-                        INSERT INTO userlist (id, name) 
-                        values(?, ?)",
-                        )
-                        .unwrap();
-                    conn.exec_iter(stmt, (newuser.id.unwrap(), newuser.name.clone()))
-                        .unwrap();
-                    let stmt = conn
-                        .prep(
-                            "
-                        INSERT INTO contest_users (id, uid) 
-                        values(?, ?)",
-                        )
-                        .unwrap();
-                    conn.exec_iter(stmt, (0, newuser.id.unwrap())).unwrap();
+                if let Some(storage) = STORAGE.lock().unwrap().as_mut() {
+                    storage
+                        .insert_user(&newuser, credential.as_ref())
+                        .map_err(storage_failure)?;
+                    // insert the new user in storage, along with contest0
                 } else {
-                    newuser.waring = Some("fail to connect to mysql".to_string());
+                    newuser.waring = Some(NOT_PERSISTED.to_string());
+                }
+                ctlist.get_mut(0).unwrap().user_ids.push(id);
+                if let Some(credential) = credential {
+                    CREDENTIALS.lock().unwrap().insert(id, credential);
                 }
-                userlist.push(newuser.clone());
-                HttpResponse::Ok().json(newuser)
+                userlist.insert(newuser.clone());
+                Ok(HttpResponse::Ok().json(Version::of(&req).body(newuser)))
             } else {
                 // no id, name exist, invalid args
-                HttpResponse::BadRequest().json(Error {
-                    code: 1,
-                    reason: String::from("ERR_INVALID_ARGUMENT"),
-                    message: String::from("User name 'root' already exists."),
-                })
+                Err(Error::invalid_argument(format!(
+                    "User name '{}' already exists.",
+                    user.name
+                )))
             }
         }
     }
 }
 /*
     function: to get all users
-    input: none
+    input: req: a HttpRequest bearing the token
     output: Responder
 */
 #[get("/users")]
-pub async fn get_users() -> impl Responder {
-    let userlist = &mut USER_LIST.lock().unwrap();
-    for i in 0..userlist.len() {
-        let mut min = i;
-        for j in i..userlist.len() {
-            if userlist[j].id.unwrap() < userlist[min].id.unwrap() {
-                min = j;
-            }
-        }
-        userlist.swap(min, i);
-    }
-    // sort the user list according to id
-    HttpResponse::Ok().json(userlist.clone())
+pub async fn get_users(req: HttpRequest) -> Result<HttpResponse, Error> {
+    authorize(&req, ANYONE)?;
+    let userlist = &USER_LIST.lock().unwrap();
+    let users: Vec<User> = userlist.iter().cloned().collect();
+    // already in the order of id
+    Ok(HttpResponse::Ok().json(Version::of(&req).body(users)))
 }
 /*
     function: to check if a contest could be founded
     input: ct: a &Contest of the contest to be checked
-    output: true if could, false if couldn't
+           config: a &Config of the global config
+           userlist: a &UserStore of all users
+    output: Ok(()) if could, otherwise an Error naming what is at fault
 */
-async fn ctcheck(ct: &Contest) -> bool {
-    let config = &CONFIG.lock().unwrap();
-    let userlist = &USER_LIST.lock().unwrap();
-    let mut pcheck = true;
-    let mut ucheck = true;
-    for i in 0..ct.problem_ids.len() {
-        let mut pcheckie = false;
-        for j in 0..config.problems.len() {
-            if ct.problem_ids[i] == config.problems[j].id {
-                pcheckie = true;
-                break;
-            }
-        }
-        if !pcheckie {
-            pcheck = false;
-            break;
+fn ctcheck(ct: &Contest, config: &Config, userlist: &UserStore) -> Result<(), Error> {
+    for pid in &ct.problem_ids {
+        if !config.problems.iter().any(|problem| problem.id == *pid) {
+            return Err(Error::not_found(format!("Problem {} not found.", pid)));
         }
     }
     // check problem ids
-    for i in 0..ct.user_ids.len() {
-        let mut ucheckie = false;
-        for j in 0..userlist.len() {
-            if ct.user_ids[i] == userlist[j].id.unwrap() {
-                ucheckie = true;
-                break;
-            }
-        }
-        if !ucheckie {
-            ucheck = false;
-            break;
+    for uid in &ct.user_ids {
+        if !userlist.contains(*uid) {
+            return Err(Error::not_found(format!("User {} not found.", uid)));
         }
     }
     // check user ids
-    pcheck
-        && ucheck
-        && NaiveDateTime::parse_from_str(&ct.from.clone(), "%Y-%m-%dT%H:%M:%S%.3fZ").is_ok()
-        && NaiveDateTime::parse_from_str(&ct.to.clone(), "%Y-%m-%dT%H:%M:%S%.3fZ").is_ok()
+    for (field, time) in [("from", &ct.from), ("to", &ct.to)] {
+        if NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S%.3fZ").is_err() {
+            return Err(Error::invalid_argument(format!(
+                "Invalid argument {}: '{}'.",
+                field, time
+            )));
+        }
+    }
+    Ok(())
 }
 /*
     function: to post a contest
     input: body: a web::Json<Contest> of the posted contest
+           req: a HttpRequest bearing the token
     output: Responder
 */
 #[post("/contests")]
-pub async fn post_contest(body: web::Json<Contest>) -> impl Responder {
+pub async fn post_contest(
+    body: web::Json<Contest>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    authorize(&req, STAFF)?;
     let mut contest = body.into_inner();
+    if matches!(&contest.rate_limit, Some(limit) if limit.period == Some(0)) {
+        return Err(Error::invalid_argument(String::from(
+            "Invalid argument rate_limit: period 0.",
+        )));
+    }
+    let config = &CONFIG.lock().unwrap();
+    let userlist = &USER_LIST.lock().unwrap();
     let ctlist = &mut CONTEST_LIST.lock().unwrap();
+    // in the order of the locks, the same as a reload of the config
     match contest.id {
+        Some(0) => Err(Error::invalid_argument(String::from(
+            "Contest 0 holds every problem and user, and cannot be changed.",
+        ))),
         Some(id) => {
-            let mut idcheck = false;
-            let mut index = 0;
-            for i in 0..ctlist.len() {
-                if ctlist[i].id.unwrap() == id {
-                    idcheck = true;
-                    index = i;
-                    break;
-                }
+            if !ctlist.contains(id) {
+                return Err(Error::not_found(format!("Contest {} not found.", id)));
             }
-            if id != 0 && idcheck && ctcheck(&contest).await {
-                // id exist and not 0, contest passed the check, update
-                let conn = &mut MYSQL.lock().unwrap();
-                if conn.is_ok() {
-                    let mut conn = conn.as_ref().unwrap().get_conn().unwrap();
-                    let stmt = conn
-                        .prep(
-                            "UPDATE contest_list
-                        SET name = ?, fromtime = ?, totime = ?, submission_limit = ?
-                        where id = ?",
-                        )
-                        .unwrap();
-                    conn.exec_iter(
-                        stmt,
-                        (
-                            contest.name.clone(),
-                            contest.from.clone(),
-                            contest.to.clone(),
-                            contest.submission_limit,
-                            contest.id,
-                        ),
-                    )
-                    .unwrap();
-                    // update contest_list in mysql
-                    let stmt = conn
-                        .prep("delete from contest_problems where id = ? ")
-                        .unwrap();
-                    conn.exec_iter(stmt, (id,)).unwrap();
-                    // delete origin contest_problems in mysql
-                    for i in 0..contest.problem_ids.len() {
-                        let stmt = conn
-                            .prep(
-                                "
-                            INSERT INTO contest_problems (id, pid) 
-                            values(?, ?)",
-                            )
-                            .unwrap();
-                        conn.exec_iter(stmt, (contest.id, contest.problem_ids[i]))
-                            .unwrap();
-                    }
-                    // insert with new problem ids
-                    let stmt = conn
-                        .prep("delete from contest_users where id = ? ")
-                        .unwrap();
-                    conn.exec_iter(stmt, (id,)).unwrap();
-                    // delete origin contest_users in mysql
-                    for i in 0..contest.user_ids.len() {
-                        let stmt = conn
-                            .prep(
-                                "
-                            INSERT INTO contest_users (id, uid) 
-                            values(?, ?)",
-                            )
-                            .unwrap();
-                        conn.exec_iter(stmt, (contest.id, contest.user_ids[i]))
-                            .unwrap();
-                    }
-                    // insert with new users
-                } else {
-                    contest.waring = Some("fail to connect to mysql".to_string());
-                }
-                ctlist[index] = contest.clone();
-                HttpResponse::Ok().json(contest)
+            ctcheck(&contest, config, userlist)?;
+            // id exist and not 0, contest passed the check, update
+            if let Some(storage) = STORAGE.lock().unwrap().as_mut() {
+                storage.update_contest(&contest).map_err(storage_failure)?;
+                // update the contest with its problems and users in storage
             } else {
-                HttpResponse::NotFound().json(Error {
-                    code: 3,
-                    reason: String::from("ERR_NOT_FOUND"),
-                    message: String::from("Contest 114514 not found."),
-                })
+                contest.waring = Some(NOT_PERSISTED.to_string());
             }
+            ctlist.insert(contest.clone());
+            Ok(HttpResponse::Ok().json(Version::of(&req).body(contest)))
         }
         None => {
-            if ctcheck(&contest).await {
-                // no id, pass the check, new contest
-                let mut max = ctlist[0].id.unwrap();
-                for i in 0..ctlist.len() {
-                    if ctlist[i].id.unwrap() > max {
-                        max = ctlist[i].id.unwrap();
-                    }
-                }
-                contest.id = Some(max + 1);
-                // id = maxid + 1
-                let conn = &mut MYSQL.lock().unwrap();
-                if conn.is_ok() {
-                    let mut conn = conn.as_ref().unwrap().get_conn().unwrap();
-                    let stmt = conn
-                        .prep(
-                            "
-                        INSERT INTO contest_list (id, name, fromtime, totime, submission_limit) 
-                        values(?, ?, ?, ?, ?)",
-                        )
-                        .unwrap();
-                    conn.exec_iter(
-                        stmt,
-                        (
-                            contest.id,
-                            contest.name.clone(),
-                            contest.from.clone(),
-                            contest.to.clone(),
-                            contest.submission_limit,
-                        ),
-                    )
-                    .unwrap();
-                    // insert new contest in contest_list in mysql
-                    for i in 0..contest.problem_ids.len() {
-                        let stmt = conn
-                            .prep(
-                                "
-                            INSERT INTO contest_problems (id, pid) 
-                            values(?, ?)",
-                            )
-                            .unwrap();
-                        conn.exec_iter(stmt, (contest.id, contest.problem_ids[i]))
-                            .unwrap();
-                    }
-                    // insert new problems in contest_problems in mysql
-                    for i in 0..contest.user_ids.len() {
-                        let stmt = conn
-                            .prep(
-                                "
-                            INSERT INTO contest_users (id, uid) 
-                            values(?, ?)",
-                            )
-                            .unwrap();
-                        conn.exec_iter(stmt, (contest.id, contest.user_ids[i]))
-                            .unwrap();
-                    }
-                    // insert new users in contest_users in mysql
-                } else {
-                    contest.waring = Some("fail to connect to mysql".to_string());
-                }
-                ctlist.push(contest.clone());
-                HttpResponse::Ok().json(contest)
+            ctcheck(&contest, config, userlist)?;
+            // no id, pass the check, new contest
+            contest.id = Some(ctlist.next_id());
+            // id = maxid + 1
+            if let Some(storage) = STORAGE.lock().unwrap().as_mut() {
+                storage.insert_contest(&contest).map_err(storage_failure)?;
+                // insert the new contest with its problems and users in storage
             } else {
-                HttpResponse::NotFound().json(Error {
-                    code: 3,
-                    reason: String::from("ERR_NOT_FOUND"),
-                    message: String::from("Contest 114514 not found."),
-                })
+                contest.waring = Some(NOT_PERSISTED.to_string());
             }
+            ctlist.insert(contest.clone());
+            Ok(HttpResponse::Ok().json(Version::of(&req).body(contest)))
         }
     }
 }
 /*
     function: to get all contests
-    input: none
+    input: req: a HttpRequest bearing the token
     output: Responder
 */
 #[get("/contests")]
-pub async fn get_contests() -> impl Responder {
-    let ctlist = &mut CONTEST_LIST.lock().unwrap();
-    let mut result = vec![];
-    if ctlist.len() != 1 {
-        for i in 1..ctlist.len() {
-            let mut min = i;
-            for j in i..ctlist.len() {
-                if ctlist[j].id.unwrap() < ctlist[min].id.unwrap() {
-                    min = j
-                }
-            }
-            ctlist.swap(min, i);
-        }
-        for i in 1..ctlist.len() {
-            result.push(ctlist[i].clone());
-        }
-    }
-    // skip the contest0
-    HttpResponse::Ok().json(result.clone())
+pub async fn get_contests(req: HttpRequest) -> Result<HttpResponse, Error> {
+    authorize(&req, ANYONE)?;
+    let ctlist = &CONTEST_LIST.lock().unwrap();
+    let result: Vec<Contest> = ctlist
+        .iter()
+        .filter(|ct| ct.id != Some(0))
+        .cloned()
+        .collect();
+    // skip the contest0, the rest are in the order of id
+    Ok(HttpResponse::Ok().json(Version::of(&req).body(result)))
 }
 /*
     function: to get a contest according to its id
     input: cid: a web::Path<String> that bears the id of the contest
+           req: a HttpRequest bearing the token
     output: Responder
 */
 #[get("/contests/{cid}")]
-pub async fn contest_id(cid: web::Path<String>) -> impl Responder {
-    let cid = cid.parse::<usize>();
-    if cid.is_ok() {
-        let id = cid.unwrap();
+pub async fn contest_id(cid: web::Path<String>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    authorize(&req, ANYONE)?;
+    if let Ok(id) = cid.parse::<usize>() {
         let ctlist = &CONTEST_LIST.lock().unwrap();
-        let mut index: Option<usize> = None;
-        for i in 0..ctlist.len() {
-            if ctlist[i].id.unwrap() == id {
-                index = Some(i);
-                break;
-            }
-        }
-        if index.is_some() {
-            return HttpResponse::Ok().json(ctlist[index.unwrap()].clone());
+        if let Some(ct) = ctlist.get(id) {
+            return Ok(HttpResponse::Ok().json(Version::of(&req).body(ct.clone())));
         }
     }
-    HttpResponse::NotFound().json(Error {
-        code: 3,
-        reason: String::from("ERR_NOT_FOUND"),
-        message: String::from("Contest 114514 not found."),
-    })
+    Err(Error::not_found(format!("Contest {} not found.", cid)))
 }
 /*
     function: to get a contest ranklist according to the id and the args
     input: cid: a web::Path<String> that bears the id of the contest
            args: a web::Query<ContestArgs> that bears the ranking rules
+           req: a HttpRequest bearing the token
     output: Responder
 */
 #[get("/contests/{cid}/ranklist")]
-pub async fn ranklist(cid: web::Path<String>, args: web::Query<ContestArgs>) -> impl Responder {
+pub async fn ranklist(
+    cid: web::Path<String>,
+    args: web::Query<ContestArgs>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    authorize(&req, ANYONE)?;
     let args = args.into_inner();
-    let cid = cid.parse::<usize>();
     let mut contest = None;
-    if cid.is_ok() {
-        let id = cid.unwrap();
+    if let Ok(id) = cid.parse::<usize>() {
         let ctlist = &CONTEST_LIST.lock().unwrap();
-        let mut index: Option<usize> = None;
-        for i in 0..ctlist.len() {
-            if ctlist[i].id.unwrap() == id {
-                index = Some(i);
-                break;
-            }
+        contest = ctlist.get(id).cloned();
+    }
+    match contest {
+        Some(contest) => {
+            let ranks = contest.contest_ranker(&args).await;
+            Ok(HttpResponse::Ok().json(Version::of(&req).body(ranks)))
+        }
+        None => Err(Error::not_found(format!("Contest {} not found.", cid))),
+        // can't find the contest of the given id
+    }
+}
+/*
+    function: to get the statements of all problems
+    input: req: a HttpRequest bearing the token
+    output: Responder
+*/
+#[get("/problems")]
+pub async fn get_problems(req: HttpRequest) -> Result<HttpResponse, Error> {
+    authorize(&req, ANYONE)?;
+    let config = &CONFIG.lock().unwrap();
+    let mut problems = vec![];
+    for problem in &config.problems {
+        problems.push(statement(problem.id, config));
+    }
+    problems.sort_by_key(|problem| problem.id);
+    Ok(HttpResponse::Ok().json(problems))
+}
+/*
+    function: to get the statement of a problem according to its id
+    input: pid: a web::Path<String> that bears the id of the problem
+           req: a HttpRequest bearing the token
+    output: Responder
+*/
+#[get("/problems/{pid}")]
+pub async fn problem_id(pid: web::Path<String>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    authorize(&req, ANYONE)?;
+    let config = &CONFIG.lock().unwrap();
+    if let Ok(id) = pid.parse::<usize>() {
+        if config.problems.iter().any(|problem| problem.id == id) {
+            return Ok(HttpResponse::Ok().json(statement(id, config)));
         }
-        if index.is_some() {
-            contest = Some(ctlist[index.unwrap()].clone());
+    }
+    Err(Error::not_found(format!("Problem {} not found.", pid)))
+}
+/*
+    function: to build the statement of a problem, showing its sample cases
+    input: pid: a usize of the id of the problem, which must be in the config
+           config: a &Config of the global config
+    output: a ProblemStatement
+*/
+fn statement(pid: usize, config: &Config) -> ProblemStatement {
+    let problem = config
+        .problems
+        .iter()
+        .find(|problem| problem.id == pid)
+        .unwrap();
+    let mut samples = vec![];
+    for (i, case) in problem.cases.iter().enumerate() {
+        if case.visibility == Some(Visibility::Sample) {
+            samples.push(Sample {
+                id: i + 1,
+                input: fread(&case.input_file, "inputfile").unwrap(),
+                answer: fread(&case.answer_file, "ansfile").unwrap(),
+            });
         }
     }
-    if contest.is_none() {
-        // can't find the contest of the given id
-        return HttpResponse::NotFound().json(Error {
-            code: 3,
-            reason: String::from("ERR_NOT_FOUND"),
-            message: String::from("Contest 114514 not found."),
-        });
+    ProblemStatement {
+        id: problem.id,
+        name: problem.name.clone(),
+        ty: problem.ty.clone(),
+        samples,
     }
-    HttpResponse::Ok().json(contest.unwrap().contest_ranker(&args).await)
 }
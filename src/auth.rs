@@ -0,0 +1,373 @@
+use super::{CONFIG, CREDENTIALS, SESSIONS, STORAGE, USER_LIST};
+use crate::{
+    error::Error,
+    structs::{Role, User},
+    version::Version,
+};
+use actix_web::{http::header, post, web, HttpRequest, HttpResponse};
+use chrono::{Duration, NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use pbkdf2::pbkdf2_hmac;
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const ADMIN: &[Role] = &[Role::Admin];
+pub const STAFF: &[Role] = &[Role::Admin, Role::ProblemSetter];
+pub const ANYONE: &[Role] = &[Role::Admin, Role::ProblemSetter, Role::Contestant];
+// who may call an endpoint, the problem setters run the contests along with the admins
+pub const ITERATIONS: u32 = 100_000;
+// the work factor of new password hashes, raising it upgrades older hashes as their users log in
+
+lazy_static! {
+    static ref DUMMY: Credential = Credential::new(usize::MAX, "");
+    // checked when the user name is unknown, so that the time taken does not tell names apart
+}
+
+/*
+    the salted hash of the password of a user, the password itself is never kept,
+    hashed with pbkdf2-hmac-sha256 over so many iterations, 0 for a single sha256 of old
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Credential {
+    pub user_id: usize,
+    pub salt: String,
+    pub hash: String,
+    #[serde(default)]
+    pub iterations: u32,
+}
+
+impl Credential {
+    /*
+        function: to hash a new password with a fresh salt
+        input: user_id: a usize of the user
+               password: a &str of the password
+        output: a Credential
+    */
+    pub fn new(user_id: usize, password: &str) -> Self {
+        let salt = random_hex(16);
+        let hash = pbkdf2(&salt, password, ITERATIONS);
+        Credential {
+            user_id,
+            salt,
+            hash,
+            iterations: ITERATIONS,
+        }
+    }
+    /*
+        function: to check a password against the credential
+        input: password: a &str of the password given
+        output: true if it is the password, false if not
+    */
+    pub fn verify(&self, password: &str) -> bool {
+        let hash = match self.iterations {
+            0 => digest(&self.salt, password),
+            iterations => pbkdf2(&self.salt, password, iterations),
+        };
+        // compare every byte, so the time taken tells nothing
+        hash.len() == self.hash.len()
+            && hash
+                .bytes()
+                .zip(self.hash.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+    /*
+        function: to check if the password should be hashed again with the current work factor
+        input: none
+        output: true if hashed with fewer iterations than ITERATIONS, false if not
+    */
+    pub fn outdated(&self) -> bool {
+        self.iterations < ITERATIONS
+    }
+}
+
+/*
+    a login of a user, valid until it expires
+*/
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub user_id: usize,
+    pub expires: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Login {
+    pub name: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Token {
+    pub token: String,
+    pub user_id: usize,
+    pub expires: String,
+}
+
+/*
+    function: to generate random bytes in hex
+    input: len: a usize of the number of bytes
+    output: a String of 2 * len hex digits
+*/
+fn random_hex(len: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..len)
+        .map(|_| format!("{:02x}", rng.gen::<u8>()))
+        .collect()
+}
+
+/*
+    function: to hash a password with its salt the old way, only to check the credentials kept before pbkdf2
+    input: salt: a &str of the salt
+           password: a &str of the password
+    output: a String of the sha256 digest in hex
+*/
+fn digest(salt: &str, password: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(password.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/*
+    function: to hash a password with its salt by pbkdf2-hmac-sha256, as in RFC 8018
+    input: salt: a &str of the salt
+           password: a &str of the password
+           iterations: a u32 of the work factor
+    output: a String of the 32-byte derived key in hex
+*/
+fn pbkdf2(salt: &str, password: &str, iterations: u32) -> String {
+    let mut derived = [0u8; 32];
+    pbkdf2_hmac::<Sha256>(
+        password.as_bytes(),
+        salt.as_bytes(),
+        iterations,
+        &mut derived,
+    );
+    derived.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/*
+    function: to tell why a request is not authenticated
+    input: message: a &str telling why
+    output: an Error of ERR_UNAUTHORIZED
+*/
+fn denied(message: &str) -> Error {
+    Error::unauthorized(String::from(message))
+}
+
+/*
+    function: to reject logging in or out when there is no auth section in the config
+    input: none
+    output: an Error of ERR_INVALID_STATE
+*/
+fn not_enabled() -> Error {
+    Error::invalid_state(String::from("Authentication is not enabled."))
+}
+
+/*
+    function: to read the token in the Authorization header
+    input: req: a &HttpRequest
+    output: Some(token) if the header bears one, None if not
+*/
+fn bearer(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim())
+}
+
+/*
+    function: to find the user a request comes from, by the token in its Authorization header
+    input: req: a &HttpRequest to be checked
+    output: Ok(None) if authentication is not enabled, Ok(Some(user id)) if the token is valid,
+            otherwise an Err telling why
+*/
+fn authenticate(req: &HttpRequest) -> Result<Option<usize>, Error> {
+    if CONFIG.lock().unwrap().auth.is_none() {
+        return Ok(None);
+    }
+    let token = match bearer(req) {
+        Some(token) => token,
+        None => return Err(denied("Missing bearer token.")),
+    };
+    let sessions = &mut SESSIONS.lock().unwrap();
+    let now = Utc::now().naive_utc();
+    sessions.retain(|_, session| session.expires > now);
+    // forget the expired sessions
+    match sessions.get(token) {
+        Some(session) => Ok(Some(session.user_id)),
+        None => Err(denied("Invalid or expired token.")),
+    }
+}
+
+/*
+    function: to check if a user holds one of the roles
+    input: user: a &User to be checked
+           roles: a &[Role] of the roles allowed
+    output: true if holds, false if not
+*/
+pub fn holds(user: &User, roles: &[Role]) -> bool {
+    roles.contains(&user.role.unwrap_or(Role::Contestant))
+}
+
+/*
+    function: to find the user a request comes from and check the role
+    input: req: a &HttpRequest to be checked
+           roles: a &[Role] of the roles allowed, like ADMIN, STAFF or ANYONE
+    output: Ok(None) if authentication is not enabled, Ok(Some(user)) if the user is allowed,
+            otherwise an Err telling why
+*/
+pub fn authorize(req: &HttpRequest, roles: &[Role]) -> Result<Option<User>, Error> {
+    let uid = match authenticate(req)? {
+        Some(uid) => uid,
+        None => return Ok(None),
+    };
+    let user = match USER_LIST.lock().unwrap().get(uid) {
+        Some(user) => user.clone(),
+        None => return Err(denied("Invalid or expired token.")),
+    };
+    if holds(&user, roles) {
+        Ok(Some(user))
+    } else {
+        Err(Error::forbidden(format!(
+            "User {} as {} is not allowed to do this.",
+            user.name,
+            user.role.unwrap_or(Role::Contestant)
+        )))
+    }
+}
+
+/*
+    function: to hash the password of a user again with the current work factor, kept as it was on failure
+    input: user_id: a usize of the user
+           password: a &str of the password just verified
+*/
+fn rehash(user_id: usize, password: &str) {
+    let credential = Credential::new(user_id, password);
+    let userlist = &USER_LIST.lock().unwrap();
+    if let (Some(user), Some(storage)) = (userlist.get(user_id), STORAGE.lock().unwrap().as_mut()) {
+        if let Err(err) = storage.update_user(user, Some(&credential)) {
+            log::warn!("Fail to store the new hash of user {}: {}", user_id, err);
+            return;
+        }
+    }
+    CREDENTIALS.lock().unwrap().insert(user_id, credential);
+}
+
+/*
+    function: to log in with the name and password of a user
+    input: body: a web::Json<Login> of the name and password
+    output: Responder
+*/
+#[post("/login")]
+pub async fn login(body: web::Json<Login>) -> Result<HttpResponse, Error> {
+    let ttl = match &CONFIG.lock().unwrap().auth {
+        Some(auth) => auth.token_ttl.unwrap_or(86400),
+        None => return Err(not_enabled()),
+    };
+    let user_id = USER_LIST.lock().unwrap().id_of(&body.name);
+    let credential = user_id.and_then(|id| CREDENTIALS.lock().unwrap().get(&id).cloned());
+    let verified = match credential {
+        Some(credential) if credential.verify(&body.password) => Some(credential.user_id),
+        Some(_) => None,
+        None => {
+            DUMMY.verify(&body.password);
+            None
+        }
+    };
+    // a user without a password cannot log in, but takes as long to be refused
+    let user_id = match verified {
+        Some(id) => id,
+        None => return Err(denied("Wrong user name or password.")),
+    };
+    if matches!(CREDENTIALS.lock().unwrap().get(&user_id), Some(credential) if credential.outdated())
+    {
+        rehash(user_id, &body.password);
+    }
+    let token = random_hex(32);
+    let expires = Utc::now().naive_utc() + Duration::seconds(ttl as i64);
+    SESSIONS
+        .lock()
+        .unwrap()
+        .insert(token.clone(), Session { user_id, expires });
+    Ok(HttpResponse::Ok().json(Token {
+        token,
+        user_id,
+        expires: expires.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+    }))
+}
+
+/*
+    function: to log out, the token of the request is no longer valid
+    input: req: a HttpRequest bearing the token
+    output: Responder, with the user logged out
+*/
+#[post("/logout")]
+pub async fn logout(req: HttpRequest) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(&req)? {
+        Some(id) => id,
+        None => return Err(not_enabled()),
+    };
+    if let Some(token) = bearer(&req) {
+        SESSIONS.lock().unwrap().remove(token);
+    }
+    let user = USER_LIST.lock().unwrap().get(user_id).cloned();
+    Ok(HttpResponse::Ok().json(Version::of(&req).body(user)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{digest, pbkdf2, Credential, ITERATIONS};
+
+    #[test]
+    fn pbkdf2_matches_the_test_vectors() {
+        assert_eq!(
+            pbkdf2("salt", "password", 1),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            pbkdf2("salt", "password", 4096),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+        assert_eq!(
+            pbkdf2(
+                "saltSALTsaltSALTsaltSALTsaltSALTsalt",
+                "passwordPASSWORDpassword",
+                4096
+            ),
+            "348c89dbcbd32b2f32d814b8116e84cf2b17347ebc1800181c4e2a1fb8dd53e1"
+        );
+        assert_eq!(
+            pbkdf2("salt", &"p".repeat(100), 2),
+            "7fb39a0c2291de62231e50ab5f6805b83bab97446d73dccf38114fb21c055427"
+        ); // a password longer than a block
+    }
+
+    #[test]
+    fn credential_verifies_only_its_password() {
+        let credential = Credential::new(1, "alice's secret");
+        assert_eq!(credential.iterations, ITERATIONS);
+        assert!(credential.verify("alice's secret"));
+        assert!(!credential.verify("alice's secret "));
+        assert!(!credential.outdated());
+    }
+
+    #[test]
+    fn old_credential_still_verifies() {
+        let credential = Credential {
+            user_id: 1,
+            salt: String::from("00ff"),
+            hash: digest("00ff", "alice's secret"),
+            iterations: 0,
+        };
+        assert!(credential.verify("alice's secret"));
+        assert!(!credential.verify("mallory's guess"));
+        assert!(credential.outdated());
+    }
+}
@@ -0,0 +1,115 @@
+use actix_web::{
+    http::{header, StatusCode},
+    HttpResponse, ResponseError,
+};
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+
+/*
+    an error of the api, answered with the status of its code and this as the json body
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Error {
+    pub code: i32,
+    pub reason: String,
+    pub message: String,
+    #[serde(skip)]
+    pub status: Option<StatusCode>,
+    #[serde(skip)]
+    pub retry_after: Option<u64>,
+}
+
+impl Error {
+    /*
+        function: to make an error of a code
+        input: code: an i32 of the code, from 1 for ERR_INVALID_ARGUMENT to 8 for ERR_FORBIDDEN
+               message: a String telling what is at fault
+        output: an Error with the reason of the code
+    */
+    pub fn new(code: i32, message: String) -> Self {
+        let reason = match code {
+            1 => "ERR_INVALID_ARGUMENT",
+            2 => "ERR_INVALID_STATE",
+            3 => "ERR_NOT_FOUND",
+            4 => "ERR_RATE_LIMIT",
+            5 => "ERR_EXTERNAL",
+            7 => "ERR_UNAUTHORIZED",
+            8 => "ERR_FORBIDDEN",
+            _ => "ERR_INTERNAL",
+        };
+        Error {
+            code,
+            reason: String::from(reason),
+            message,
+            status: None,
+            retry_after: None,
+        }
+    }
+    pub fn invalid_argument(message: String) -> Self {
+        Self::new(1, message)
+    }
+    pub fn invalid_state(message: String) -> Self {
+        Self::new(2, message)
+    }
+    pub fn not_found(message: String) -> Self {
+        Self::new(3, message)
+    }
+    pub fn rate_limit(message: String) -> Self {
+        Self::new(4, message)
+    }
+    pub fn internal(message: String) -> Self {
+        Self::new(6, message)
+    }
+    pub fn unauthorized(message: String) -> Self {
+        Self::new(7, message)
+    }
+    pub fn forbidden(message: String) -> Self {
+        Self::new(8, message)
+    }
+    /*
+        function: to answer with another status than that of the code
+        input: status: a StatusCode, like 503 for a server shutting down
+        output: the Error
+    */
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = Some(status);
+        self
+    }
+    /*
+        function: to tell the client when to try again, in a Retry-After header
+        input: secs: a u64 of the seconds to wait
+        output: the Error, answered with 429
+    */
+    pub fn retry_after(mut self, secs: u64) -> Self {
+        self.retry_after = Some(secs);
+        self.with_status(StatusCode::TOO_MANY_REQUESTS)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.reason, self.message)
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        self.status.unwrap_or(match self.code {
+            1 | 2 | 4 => StatusCode::BAD_REQUEST,
+            3 => StatusCode::NOT_FOUND,
+            7 => StatusCode::UNAUTHORIZED,
+            8 => StatusCode::FORBIDDEN,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })
+    }
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if self.code == 7 {
+            response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+        } // ask for a bearer token
+        if let Some(secs) = self.retry_after {
+            response.insert_header((header::RETRY_AFTER, secs.to_string()));
+        }
+        response.json(self)
+    }
+}
@@ -0,0 +1,209 @@
+use crate::config::RateLimit;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+/*
+    whose submissions a bucket counts
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Client {
+    User(usize),
+    Ip(IpAddr),
+}
+
+/*
+    a token bucket, refilled at a steady rate up to its capacity
+*/
+#[derive(Debug, Clone)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/*
+    a bucket to take a token from: its key, capacity and period in seconds
+*/
+type Wanted = ((Option<usize>, Client), f64, f64);
+
+/*
+    function: to find the buckets a submission falls in
+    input: limits: a &[(Option<usize>, &RateLimit)] of the limits to apply, by contest id
+           user: a usize of the user submitting
+           ip: an Option<IpAddr> of the client, None when unknown
+    output: a Vec<Wanted> of the buckets
+*/
+fn wanted(limits: &[(Option<usize>, &RateLimit)], user: usize, ip: Option<IpAddr>) -> Vec<Wanted> {
+    let mut wanted = vec![];
+    for (scope, limit) in limits {
+        let period = limit.period.unwrap_or(60) as f64;
+        if let Some(capacity) = limit.per_user {
+            wanted.push(((*scope, Client::User(user)), capacity as f64, period));
+        }
+        if let (Some(capacity), Some(ip)) = (limit.per_ip, ip) {
+            wanted.push(((*scope, Client::Ip(ip)), capacity as f64, period));
+        }
+    }
+    wanted
+}
+
+/*
+    function: to find the address of the client behind the reverse proxies
+    input: peer: an Option<IpAddr> of the peer, None over a unix socket
+           forwarded: an Option<&str> of the X-Forwarded-For header
+           trusted: a &[IpAddr] of the proxies whose X-Forwarded-For is believed
+    output: the peer if it is not a proxy, otherwise the last address in X-Forwarded-For
+            that is not a trusted proxy, None if unknown
+*/
+pub fn client_ip(
+    peer: Option<IpAddr>,
+    forwarded: Option<&str>,
+    trusted: &[IpAddr],
+) -> Option<IpAddr> {
+    let proxy = match peer {
+        Some(peer) => trusted.contains(&peer),
+        None => true,
+    }; // only the reverse proxy reaches the unix socket
+    let forwarded = match forwarded {
+        Some(forwarded) if proxy => forwarded,
+        _ => return peer,
+    };
+    let mut client = peer;
+    for address in forwarded.rsplit(',') {
+        let ip = match address.trim().parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => return None,
+        };
+        client = Some(ip);
+        if !trusted.contains(&ip) {
+            break;
+        }
+    } // each proxy appends the address it got the request from, the client's is the last one not a proxy
+    client
+}
+
+/*
+    the buckets of all clients, keyed by the contest limiting them, None for the global limit
+*/
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: HashMap<(Option<usize>, Client), Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /*
+        function: to take a token from every bucket a submission falls in, or from none of them
+        input: limits: a &[(Option<usize>, &RateLimit)] of the limits to apply, by contest id
+               user: a usize of the user submitting
+               ip: an Option<IpAddr> of the client, None over a unix socket
+        output: Ok(()) if the submission is allowed, otherwise Err(seconds to wait)
+    */
+    pub fn take(
+        &mut self,
+        limits: &[(Option<usize>, &RateLimit)],
+        user: usize,
+        ip: Option<IpAddr>,
+    ) -> Result<(), u64> {
+        let now = Instant::now();
+        if self.buckets.len() > 10000 {
+            self.buckets.retain(|_, bucket| {
+                now.duration_since(bucket.updated) < Duration::from_secs(86400)
+            });
+        } // forget the clients idle for a day
+        let wanted = wanted(limits, user, ip);
+        let mut wait: f64 = 0.0;
+        for (key, capacity, period) in &wanted {
+            let rate = capacity / period;
+            let bucket = self.buckets.entry(*key).or_insert(Bucket {
+                tokens: *capacity,
+                updated: now,
+            });
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * rate).min(*capacity);
+            bucket.updated = now;
+            if bucket.tokens < 1.0 {
+                wait = wait.max(if rate > 0.0 {
+                    (1.0 - bucket.tokens) / rate
+                } else {
+                    *period
+                });
+            }
+        } // refill every bucket first
+        if wait > 0.0 {
+            return Err(wait.ceil() as u64);
+        }
+        for (key, _, _) in &wanted {
+            if let Some(bucket) = self.buckets.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+    /*
+        function: to give back the tokens of a submission that was taken but then failed
+        input: limits, user and ip: as given to take
+    */
+    pub fn refund(
+        &mut self,
+        limits: &[(Option<usize>, &RateLimit)],
+        user: usize,
+        ip: Option<IpAddr>,
+    ) {
+        for (key, capacity, _) in wanted(limits, user, ip) {
+            if let Some(bucket) = self.buckets.get_mut(&key) {
+                bucket.tokens = (bucket.tokens + 1.0).min(capacity);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{client_ip, RateLimiter};
+    use crate::config::RateLimit;
+    use std::net::IpAddr;
+
+    fn ip(address: &str) -> Option<IpAddr> {
+        address.parse().ok()
+    }
+
+    #[test]
+    fn refund_gives_the_token_back() {
+        let limit = RateLimit {
+            per_user: Some(1),
+            per_ip: None,
+            period: Some(3600),
+        };
+        let limits = [(None, &limit)];
+        let limiter = &mut RateLimiter::new();
+        assert_eq!(limiter.take(&limits, 1, None), Ok(()));
+        limiter.refund(&limits, 1, None);
+        assert_eq!(limiter.take(&limits, 1, None), Ok(()));
+        assert!(limiter.take(&limits, 1, None).is_err());
+    }
+
+    #[test]
+    fn client_ip_believes_only_trusted_proxies() {
+        let trusted = [ip("10.0.0.1").unwrap()];
+        let forwarded = Some("203.0.113.9, 198.51.100.7, 10.0.0.1");
+        assert_eq!(
+            client_ip(ip("192.0.2.1"), forwarded, &trusted),
+            ip("192.0.2.1")
+        );
+        assert_eq!(
+            client_ip(ip("10.0.0.1"), forwarded, &trusted),
+            ip("198.51.100.7")
+        );
+        assert_eq!(client_ip(ip("10.0.0.1"), None, &trusted), ip("10.0.0.1"));
+        assert_eq!(
+            client_ip(None, Some("198.51.100.7"), &[]),
+            ip("198.51.100.7")
+        );
+        assert_eq!(client_ip(None, None, &trusted), None);
+    }
+}
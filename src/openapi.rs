@@ -0,0 +1,16 @@
+use actix_web::{get, http::header::ContentType, HttpResponse, Responder};
+
+const SPEC: &str = include_str!("openapi.json");
+// written by hand, the tests check it against the routes
+
+/*
+    function: to publish the OpenAPI document of every route, open to anyone
+    input: none
+    output: Responder
+*/
+#[get("/openapi.json")]
+pub async fn get_openapi() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(SPEC)
+}
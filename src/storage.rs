@@ -0,0 +1,1881 @@
+use crate::{
+    auth::Credential,
+    config::{masked, Backend, Database, RateLimit},
+    structs::{
+        string2result, string2role, string2state, Attempt, Case, Contest, JobRequest, JobResponse,
+        Role, User,
+    },
+};
+use chrono::Utc;
+use mysql::prelude::*;
+use mysql::{Opts, OptsBuilder, Pool, Transaction, TxOpts};
+use rusqlite::{params, Connection};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    time::Duration,
+};
+
+pub type StorageError = Box<dyn Error + Send + Sync>;
+pub type StorageResult<T> = Result<T, StorageError>;
+
+/*
+    a versioned change of the schema, with the statements for each backend
+*/
+struct Migration {
+    version: u32,
+    description: &'static str,
+    mysql: &'static [&'static str],
+    sqlite: &'static [&'static str],
+}
+
+/*
+    all migrations in order, each one is applied once and recorded in schema_version,
+    append new ones at the end and never edit those already released,
+    mysql cannot roll back a migration stopped halfway, so its statements have to be safe to run again
+*/
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create the initial tables",
+        mysql: &[
+            "CREATE TABLE IF NOT EXISTS userlist (id INT NOT NULL, name VARCHAR(255) NOT NULL)",
+            "CREATE TABLE IF NOT EXISTS joblist (
+                id INT NOT NULL, create_time VARCHAR(32) NOT NULL, update_time VARCHAR(32) NOT NULL,
+                state VARCHAR(32) NOT NULL, result VARCHAR(64) NOT NULL, score DOUBLE NOT NULL
+            )",
+            "CREATE TABLE IF NOT EXISTS job_submit (
+                id INT NOT NULL, source_code MEDIUMTEXT NOT NULL, language VARCHAR(255) NOT NULL,
+                user_id INT NOT NULL, contest_id INT NOT NULL, problem_id INT NOT NULL
+            )",
+            "CREATE TABLE IF NOT EXISTS job_cases (
+                jobid INT NOT NULL, caseid INT NOT NULL, result VARCHAR(64) NOT NULL,
+                time BIGINT UNSIGNED NOT NULL, memory DOUBLE NOT NULL, info TEXT NOT NULL
+            )",
+            "CREATE TABLE IF NOT EXISTS contest_list (
+                id INT NOT NULL, name VARCHAR(255) NOT NULL, fromtime VARCHAR(32) NOT NULL,
+                totime VARCHAR(32) NOT NULL, submission_limit INT NOT NULL
+            )",
+            "CREATE TABLE IF NOT EXISTS contest_problems (id INT NOT NULL, pid INT NOT NULL)",
+            "CREATE TABLE IF NOT EXISTS contest_users (id INT NOT NULL, uid INT NOT NULL)",
+        ],
+        sqlite: &[
+            "CREATE TABLE IF NOT EXISTS userlist (id INTEGER NOT NULL, name TEXT NOT NULL)",
+            "CREATE TABLE IF NOT EXISTS joblist (
+                id INTEGER NOT NULL, create_time TEXT NOT NULL, update_time TEXT NOT NULL,
+                state TEXT NOT NULL, result TEXT NOT NULL, score REAL NOT NULL
+            )",
+            "CREATE TABLE IF NOT EXISTS job_submit (
+                id INTEGER NOT NULL, source_code TEXT NOT NULL, language TEXT NOT NULL,
+                user_id INTEGER NOT NULL, contest_id INTEGER NOT NULL, problem_id INTEGER NOT NULL
+            )",
+            "CREATE TABLE IF NOT EXISTS job_cases (
+                jobid INTEGER NOT NULL, caseid INTEGER NOT NULL, result TEXT NOT NULL,
+                time INTEGER NOT NULL, memory REAL NOT NULL, info TEXT NOT NULL
+            )",
+            "CREATE TABLE IF NOT EXISTS contest_list (
+                id INTEGER NOT NULL, name TEXT NOT NULL, fromtime TEXT NOT NULL,
+                totime TEXT NOT NULL, submission_limit INTEGER NOT NULL
+            )",
+            "CREATE TABLE IF NOT EXISTS contest_problems (id INTEGER NOT NULL, pid INTEGER NOT NULL)",
+            "CREATE TABLE IF NOT EXISTS contest_users (id INTEGER NOT NULL, uid INTEGER NOT NULL)",
+        ],
+    },
+    Migration {
+        version: 2,
+        description: "index the rows looked up by job and contest",
+        mysql: &[
+            "CREATE INDEX job_cases_jobid ON job_cases (jobid)",
+            "CREATE INDEX contest_problems_id ON contest_problems (id)",
+            "CREATE INDEX contest_users_id ON contest_users (id)",
+        ],
+        sqlite: &[
+            "CREATE INDEX IF NOT EXISTS job_cases_jobid ON job_cases (jobid)",
+            "CREATE INDEX IF NOT EXISTS contest_problems_id ON contest_problems (id)",
+            "CREATE INDEX IF NOT EXISTS contest_users_id ON contest_users (id)",
+        ],
+    },
+    Migration {
+        version: 3,
+        description: "keep every judging of a job as an attempt",
+        mysql: &[
+            "CREATE TABLE IF NOT EXISTS job_attempts (
+                jobid INT NOT NULL, attempt INT NOT NULL, judged_time VARCHAR(32) NOT NULL,
+                result VARCHAR(64) NOT NULL, score DOUBLE NOT NULL
+            )",
+            "CREATE TABLE IF NOT EXISTS attempt_cases (
+                jobid INT NOT NULL, attempt INT NOT NULL, caseid INT NOT NULL,
+                result VARCHAR(64) NOT NULL, time BIGINT UNSIGNED NOT NULL, memory DOUBLE NOT NULL,
+                info TEXT NOT NULL
+            )",
+            "CREATE INDEX job_attempts_jobid ON job_attempts (jobid)",
+            "CREATE INDEX attempt_cases_jobid ON attempt_cases (jobid)",
+            "INSERT INTO job_attempts (jobid, attempt, judged_time, result, score)
+            SELECT id, 0, update_time, result, score FROM joblist WHERE state = 'Finished'
+            AND NOT EXISTS (SELECT 1 FROM job_attempts WHERE jobid = joblist.id)",
+            "INSERT INTO attempt_cases (jobid, attempt, caseid, result, time, memory, info)
+            SELECT jobid, 0, caseid, job_cases.result, time, memory, info
+            FROM job_cases JOIN joblist ON joblist.id = job_cases.jobid
+            WHERE joblist.state = 'Finished'
+            AND NOT EXISTS (SELECT 1 FROM attempt_cases WHERE attempt_cases.jobid = joblist.id)",
+        ],
+        sqlite: &[
+            "CREATE TABLE IF NOT EXISTS job_attempts (
+                jobid INTEGER NOT NULL, attempt INTEGER NOT NULL, judged_time TEXT NOT NULL,
+                result TEXT NOT NULL, score REAL NOT NULL
+            )",
+            "CREATE TABLE IF NOT EXISTS attempt_cases (
+                jobid INTEGER NOT NULL, attempt INTEGER NOT NULL, caseid INTEGER NOT NULL,
+                result TEXT NOT NULL, time INTEGER NOT NULL, memory REAL NOT NULL, info TEXT NOT NULL
+            )",
+            "CREATE INDEX IF NOT EXISTS job_attempts_jobid ON job_attempts (jobid)",
+            "CREATE INDEX IF NOT EXISTS attempt_cases_jobid ON attempt_cases (jobid)",
+            "INSERT INTO job_attempts (jobid, attempt, judged_time, result, score)
+            SELECT id, 0, update_time, result, score FROM joblist WHERE state = 'Finished'",
+            "INSERT INTO attempt_cases (jobid, attempt, caseid, result, time, memory, info)
+            SELECT jobid, 0, caseid, job_cases.result, time, memory, info
+            FROM job_cases JOIN joblist ON joblist.id = job_cases.jobid
+            WHERE joblist.state = 'Finished'",
+        ],
+    },
+    Migration {
+        version: 4,
+        description: "keep the credentials of users",
+        mysql: &["CREATE TABLE IF NOT EXISTS user_credentials (
+                id INT NOT NULL, salt VARCHAR(64) NOT NULL, hash VARCHAR(64) NOT NULL
+            )"],
+        sqlite: &["CREATE TABLE IF NOT EXISTS user_credentials (
+                id INTEGER NOT NULL, salt TEXT NOT NULL, hash TEXT NOT NULL
+            )"],
+    },
+    Migration {
+        version: 5,
+        description: "give every user a role, root is the admin",
+        mysql: &[
+            "ALTER TABLE userlist ADD COLUMN role VARCHAR(32) NOT NULL DEFAULT 'contestant'",
+            "UPDATE userlist SET role = 'admin' WHERE id = 0",
+        ],
+        sqlite: &[
+            "ALTER TABLE userlist ADD COLUMN role TEXT NOT NULL DEFAULT 'contestant'",
+            "UPDATE userlist SET role = 'admin' WHERE id = 0",
+        ],
+    },
+    Migration {
+        version: 6,
+        description: "let a contest limit the rate of submissions",
+        mysql: &["ALTER TABLE contest_list ADD COLUMN rate_per_user INT NULL,
+            ADD COLUMN rate_per_ip INT NULL, ADD COLUMN rate_period BIGINT NULL"],
+        sqlite: &[
+            "ALTER TABLE contest_list ADD COLUMN rate_per_user INTEGER",
+            "ALTER TABLE contest_list ADD COLUMN rate_per_ip INTEGER",
+            "ALTER TABLE contest_list ADD COLUMN rate_period INTEGER",
+        ],
+    },
+    Migration {
+        version: 7,
+        description: "keep the work factor of every password hash, 0 for a single sha256",
+        mysql: &["ALTER TABLE user_credentials ADD COLUMN iterations INT NOT NULL DEFAULT 0"],
+        sqlite: &["ALTER TABLE user_credentials ADD COLUMN iterations INTEGER NOT NULL DEFAULT 0"],
+    },
+];
+
+const ER_DUP_FIELDNAME: u16 = 1060;
+const ER_DUP_KEYNAME: u16 = 1061;
+// the errors of mysql for a column or an index that is already there, left by a migration stopped halfway
+
+const CURRENT_VERSION: &str = "SELECT COALESCE(MAX(version), 0) FROM schema_version";
+// 0 for an empty table, where MAX alone gives a row of NULL
+
+/*
+    function: to find the migrations still to be applied
+    input: current: a u32 of the version recorded in the database, 0 for a new one
+    output: the pending migrations in order, or an err when the database is newer than the server
+*/
+fn pending(current: u32) -> StorageResult<Vec<&'static Migration>> {
+    let latest = MIGRATIONS.last().map_or(0, |m| m.version);
+    if current > latest {
+        return Err(format!(
+            "database schema version {} is newer than the latest known version {}",
+            current, latest
+        )
+        .into());
+    }
+    Ok(MIGRATIONS.iter().filter(|m| m.version > current).collect())
+}
+
+/*
+    rows that an interrupted write may leave behind, with the statement removing them,
+    jobs without a submission go first so that their cases are caught afterwards
+*/
+const ORPHANS: &[(&str, &str)] = &[
+    (
+        "jobs without a submission",
+        "DELETE FROM joblist WHERE id NOT IN (SELECT id FROM job_submit)",
+    ),
+    (
+        "submissions without a job",
+        "DELETE FROM job_submit WHERE id NOT IN (SELECT id FROM joblist)",
+    ),
+    (
+        "cases without a job",
+        "DELETE FROM job_cases WHERE jobid NOT IN (SELECT id FROM joblist)",
+    ),
+    (
+        "attempts without a job",
+        "DELETE FROM job_attempts WHERE jobid NOT IN (SELECT id FROM joblist)",
+    ),
+    (
+        "cases of an attempt without a job",
+        "DELETE FROM attempt_cases WHERE jobid NOT IN (SELECT id FROM joblist)",
+    ),
+    (
+        "problems of a missing contest",
+        "DELETE FROM contest_problems WHERE id NOT IN (SELECT id FROM contest_list)",
+    ),
+    (
+        "users of a missing contest",
+        "DELETE FROM contest_users WHERE id NOT IN (SELECT id FROM contest_list)",
+    ),
+    (
+        "missing users of a contest",
+        "DELETE FROM contest_users WHERE uid NOT IN (SELECT id FROM userlist)",
+    ),
+    (
+        "credentials of a missing user",
+        "DELETE FROM user_credentials WHERE id NOT IN (SELECT id FROM userlist)",
+    ),
+];
+
+/*
+    the persistent storage behind USER_LIST, JOB_LIST and CONTEST_LIST,
+    every write is issued right after the in-memory list is changed
+*/
+pub trait Storage: Send {
+    /*
+        function: to create the schema and apply the pending migrations
+        output: the schema version after migrating
+    */
+    fn migrate(&mut self) -> StorageResult<u32>;
+    /*
+        function: to clear all data, as requested by '-f'
+    */
+    fn flush(&mut self) -> StorageResult<()>;
+    /*
+        function: to make sure user0 "root" and contest0 with all problems are stored
+        input: problem_ids: a &[usize] of all problem ids in the config
+    */
+    fn init(&mut self, problem_ids: &[usize]) -> StorageResult<()>;
+    /*
+        function: to remove the orphaned rows in one transaction before loading
+        output: a vec of (description, count) of the rows removed
+    */
+    fn repair(&mut self) -> StorageResult<Vec<(&'static str, usize)>>;
+    fn load_users(&mut self) -> StorageResult<Vec<User>>;
+    fn load_jobs(&mut self) -> StorageResult<Vec<JobResponse>>;
+    fn load_contests(&mut self) -> StorageResult<Vec<Contest>>;
+    /*
+        function: to load the attempts of all jobs
+        output: a vec of Attempt sorted by job id and then attempt id
+    */
+    fn load_attempts(&mut self) -> StorageResult<Vec<Attempt>>;
+    fn load_credentials(&mut self) -> StorageResult<Vec<Credential>>;
+    fn insert_job(&mut self, job: &JobResponse) -> StorageResult<()>;
+    /*
+        function: to replace the result of a job after rejudging, the submission is kept
+    */
+    fn update_job(&mut self, job: &JobResponse) -> StorageResult<()>;
+    /*
+        function: to store the result of a judging, together with it as a new attempt
+        input: job: a &JobResponse just judged
+               attempt: an &Attempt of the same result
+    */
+    fn finish_job(&mut self, job: &JobResponse, attempt: &Attempt) -> StorageResult<()>;
+    /*
+        function: to remove a job along with its attempts
+    */
+    fn delete_job(&mut self, id: usize) -> StorageResult<()>;
+    /*
+        function: to store a new user, who joins contest0 at the same time
+        input: user: a &User to be stored
+               credential: Some(&Credential) if a password is given along
+    */
+    fn insert_user(&mut self, user: &User, credential: Option<&Credential>) -> StorageResult<()>;
+    /*
+        function: to rename a user or change the role,
+                  and replace the credential if a password is given along
+    */
+    fn update_user(&mut self, user: &User, credential: Option<&Credential>) -> StorageResult<()>;
+    fn insert_contest(&mut self, contest: &Contest) -> StorageResult<()>;
+    fn update_contest(&mut self, contest: &Contest) -> StorageResult<()>;
+    /*
+        function: to write everything stored so far in one place, done periodically and on shutdown,
+                  only meaningful to backends keeping a journal
+    */
+    fn checkpoint(&mut self) -> StorageResult<()> {
+        Ok(())
+    }
+}
+
+/*
+    function: to assemble JobResponses from the rows of joblist, job_submit and job_cases,
+              joined by job id, a job or a submission missing its counterpart is reported and skipped
+    input: jobs: rows of (id, create_time, update_time, state, result, score)
+           submits: rows of (id, source_code, language, user_id, contest_id, problem_id)
+           allcases: rows of (jobid, caseid, result, time, memory, info)
+    output: a vec of JobResponse sorted by id
+*/
+fn assemble_jobs(
+    jobs: Vec<(usize, String, String, String, String, f64)>,
+    submits: Vec<(usize, String, String, usize, usize, usize)>,
+    allcases: Vec<(usize, usize, String, u128, f64, String)>,
+) -> StorageResult<Vec<JobResponse>> {
+    let mut submissions: HashMap<usize, JobRequest> = HashMap::new();
+    for submit in submits {
+        submissions.insert(
+            submit.0,
+            JobRequest {
+                source_code: submit.1,
+                language: submit.2,
+                user_id: Some(submit.3),
+                contest_id: submit.4,
+                problem_id: submit.5,
+            },
+        );
+    }
+    // submissions keyed by job id
+    let mut jobcases: HashMap<usize, Vec<Case>> = HashMap::new();
+    for case in allcases {
+        jobcases.entry(case.0).or_default().push(Case {
+            id: case.1,
+            result: string2result(&case.2)?,
+            time: case.3,
+            memory: case.4,
+            info: case.5,
+        });
+    }
+    // cases keyed by job id
+    let mut joblist = vec![];
+    for job in jobs {
+        let submission = match submissions.remove(&job.0) {
+            Some(submission) => submission,
+            None => {
+                log::warn!("Job {} has no submission in storage, skipped", job.0);
+                continue;
+            }
+        };
+        let mut cases = jobcases.remove(&job.0).unwrap_or_default();
+        cases.sort_by_key(|case| case.id);
+        joblist.push(JobResponse {
+            id: job.0,
+            created_time: job.1,
+            updated_time: job.2,
+            submission,
+            state: string2state(&job.3)?,
+            result: string2result(&job.4)?,
+            score: job.5,
+            cases,
+            warning: None,
+        });
+    }
+    for id in submissions.keys() {
+        log::warn!("Submission {} has no job in storage, skipped", id);
+    }
+    joblist.sort_by_key(|job| job.id);
+    // rows come back in no particular order
+    Ok(joblist)
+}
+/*
+    function: to assemble Attempts from the rows of job_attempts and attempt_cases,
+              joined by job id and attempt id
+    input: attempts: rows of (jobid, attempt, judged_time, result, score)
+           allcases: rows of (jobid, attempt, caseid, result, time, memory, info)
+    output: a vec of Attempt sorted by job id and then attempt id
+*/
+fn assemble_attempts(
+    attempts: Vec<(usize, usize, String, String, f64)>,
+    allcases: Vec<(usize, usize, usize, String, u128, f64, String)>,
+) -> StorageResult<Vec<Attempt>> {
+    let mut attemptcases: HashMap<(usize, usize), Vec<Case>> = HashMap::new();
+    for case in allcases {
+        attemptcases
+            .entry((case.0, case.1))
+            .or_default()
+            .push(Case {
+                id: case.2,
+                result: string2result(&case.3)?,
+                time: case.4,
+                memory: case.5,
+                info: case.6,
+            });
+    }
+    // cases keyed by job id and attempt id
+    let mut list = vec![];
+    for attempt in attempts {
+        let mut cases = attemptcases
+            .remove(&(attempt.0, attempt.1))
+            .unwrap_or_default();
+        cases.sort_by_key(|case| case.id);
+        list.push(Attempt {
+            job_id: attempt.0,
+            id: attempt.1,
+            judged_time: attempt.2,
+            result: string2result(&attempt.3)?,
+            score: attempt.4,
+            cases,
+        });
+    }
+    list.sort_by_key(|attempt| (attempt.job_id, attempt.id));
+    Ok(list)
+}
+/*
+    a row of contest_list: id, name, fromtime, totime, submission_limit,
+    rate_per_user, rate_per_ip and rate_period
+*/
+type ContestRow = (
+    usize,
+    String,
+    String,
+    String,
+    usize,
+    Option<u32>,
+    Option<u32>,
+    Option<u64>,
+);
+
+/*
+    function: to split the rate limit of a contest into the columns of contest_list
+    input: contest: a &Contest to be stored
+    output: a tuple of (rate_per_user, rate_per_ip, rate_period)
+*/
+fn rate_columns(contest: &Contest) -> (Option<u32>, Option<u32>, Option<u64>) {
+    match &contest.rate_limit {
+        Some(limit) => (limit.per_user, limit.per_ip, limit.period),
+        None => (None, None, None),
+    }
+}
+
+/*
+    function: to assemble Contests from the rows of contest_list, contest_problems and contest_users
+    input: cts: rows of contest_list
+           pids: rows of (id, pid)
+           uids: rows of (id, uid)
+    output: a vec of Contest
+*/
+fn assemble_contests(
+    cts: Vec<ContestRow>,
+    pids: Vec<(usize, usize)>,
+    uids: Vec<(usize, usize)>,
+) -> Vec<Contest> {
+    let mut ctlist = vec![];
+    for ct in cts {
+        let problem_ids = pids.iter().filter(|p| p.0 == ct.0).map(|p| p.1).collect();
+        let user_ids = uids.iter().filter(|u| u.0 == ct.0).map(|u| u.1).collect();
+        ctlist.push(Contest {
+            id: Some(ct.0),
+            name: ct.1,
+            from: ct.2,
+            to: ct.3,
+            problem_ids,
+            user_ids,
+            submission_limit: ct.4,
+            rate_limit: match (ct.5, ct.6, ct.7) {
+                (None, None, None) => None,
+                (per_user, per_ip, period) => Some(RateLimit {
+                    per_user,
+                    per_ip,
+                    period,
+                }),
+            },
+            waring: None,
+        });
+    }
+    ctlist
+}
+
+/*
+    function: to open the storage configured in the database section
+    input: database: a &Option<Database> of the database section in config
+    output: a Ok(Some(storage)), a Ok(None) when running in memory, or an err with the reason
+*/
+pub fn open(database: &Option<Database>) -> Result<Option<Box<dyn Storage>>, String> {
+    let database = match database {
+        Some(database) => database,
+        None => return Ok(None),
+    };
+    let mut storage: Box<dyn Storage> = match database.backend {
+        Some(Backend::Sqlite) => {
+            let path = database
+                .path
+                .clone()
+                .unwrap_or_else(|| String::from("oj.db"));
+            log::info!("Opening sqlite database {}", path);
+            match SqliteStorage::open(&path) {
+                Ok(storage) => Box::new(storage),
+                Err(err) => return Err(format!("Fail to open sqlite database {}: {}", path, err)),
+            }
+        }
+        Some(Backend::Json) => {
+            let path = database
+                .path
+                .clone()
+                .unwrap_or_else(|| String::from("oj.json"));
+            log::info!("Opening json snapshot {}", path);
+            match JsonStorage::open(&path) {
+                Ok(storage) => Box::new(storage),
+                Err(err) => return Err(format!("Fail to open json snapshot {}: {}", path, err)),
+            }
+        }
+        Some(Backend::Mysql) | None => match database.url.as_ref() {
+            Some(url) => Box::new(MysqlStorage::connect(url, database)?),
+            None => return Ok(None),
+        },
+    };
+    match storage.migrate() {
+        Ok(version) => log::info!("Database schema is at version {}", version),
+        Err(err) => return Err(format!("Fail to migrate the database schema: {}", err)),
+    }
+    // create the schema on first start and upgrade it afterwards
+    Ok(Some(storage))
+}
+
+pub struct MysqlStorage {
+    pool: Pool,
+}
+
+impl MysqlStorage {
+    /*
+        function: to connect to a mysql database
+        input: url: a &str of the database url
+               database: a &Database of the database section in config
+        output: a MysqlStorage, or an err with the masked url and the reason
+    */
+    pub fn connect(url: &str, database: &Database) -> Result<Self, String> {
+        let opts = match Opts::from_url(url) {
+            Ok(opts) => opts,
+            Err(err) => return Err(format!("Invalid database url {}: {}", masked(url), err)),
+        };
+        let opts = OptsBuilder::from_opts(opts)
+            .tcp_connect_timeout(database.connect_timeout.map(Duration::from_secs))
+            .read_timeout(database.read_timeout.map(Duration::from_secs))
+            .write_timeout(database.write_timeout.map(Duration::from_secs));
+        log::info!("Connecting to database {}", masked(url));
+        match Pool::new_manual(
+            database.pool_min.unwrap_or(10),
+            database.pool_max.unwrap_or(100),
+            opts,
+        ) {
+            Ok(pool) => Ok(MysqlStorage { pool }),
+            Err(err) => Err(format!(
+                "Fail to connect to database {}: {}",
+                masked(url),
+                err
+            )),
+        }
+    }
+    /*
+        function: to replace the credential of a user
+        input: tx: a &mut Transaction
+               credential: a &Credential to be stored
+    */
+    fn replace_credential(tx: &mut Transaction, credential: &Credential) -> StorageResult<()> {
+        tx.exec_drop(
+            "delete from user_credentials where id = ? ",
+            (credential.user_id,),
+        )?;
+        tx.exec_drop(
+            "INSERT INTO user_credentials (id, salt, hash, iterations) values(?, ?, ?, ?)",
+            (
+                credential.user_id,
+                credential.salt.clone(),
+                credential.hash.clone(),
+                credential.iterations,
+            ),
+        )?;
+        Ok(())
+    }
+    /*
+        function: to replace the cases and the row of a job in joblist
+        input: tx: a &mut Transaction
+               job: a &JobResponse to be stored
+    */
+    fn replace_job(tx: &mut Transaction, job: &JobResponse) -> StorageResult<()> {
+        tx.exec_drop("delete from job_cases where jobid = ? ", (job.id,))?;
+        // delete the origin job_cases in mysql
+        for (i, case) in job.cases.iter().enumerate() {
+            tx.exec_drop(
+                "
+                INSERT INTO job_cases (jobid, caseid, result, time, memory, info)
+                values(?, ?, ?, ?, ?, ?)",
+                (
+                    job.id,
+                    i,
+                    case.result.to_string(),
+                    case.time,
+                    case.memory,
+                    case.info.clone(),
+                ),
+            )?;
+        }
+        // insert with new job_cases
+        tx.exec_drop("delete from joblist where id = ? ", (job.id,))?;
+        // delete the origin job in joblist in mysql
+        tx.exec_drop(
+            "
+            INSERT INTO joblist (id, create_time, update_time, state, result, score)
+            values(?, ?, ?, ?, ?, ?)",
+            (
+                job.id,
+                job.created_time.clone(),
+                job.updated_time.clone(),
+                job.state.to_string(),
+                job.result.to_string(),
+                job.score,
+            ),
+        )?;
+        // insert with new job
+        Ok(())
+    }
+}
+
+impl Storage for MysqlStorage {
+    fn migrate(&mut self) -> StorageResult<u32> {
+        let mut conn = self.pool.get_conn()?;
+        conn.query_drop(
+            "CREATE TABLE IF NOT EXISTS schema_version (
+                version INT NOT NULL PRIMARY KEY, description VARCHAR(255) NOT NULL,
+                applied_time VARCHAR(32) NOT NULL
+            )",
+        )?;
+        let current: Option<u32> = conn.query_first(CURRENT_VERSION)?;
+        let mut version = current.unwrap_or(0);
+        for migration in pending(version)? {
+            log::info!(
+                "Applying migration {}: {}",
+                migration.version,
+                migration.description
+            );
+            for statement in migration.mysql {
+                match conn.query_drop(statement) {
+                    Err(mysql::Error::MySqlError(err))
+                        if err.code == ER_DUP_FIELDNAME || err.code == ER_DUP_KEYNAME =>
+                    {
+                        log::warn!("Already applied: {}", err.message);
+                    }
+                    result => result?,
+                }
+            }
+            // mysql commits DDL implicitly, so each migration is recorded right after it
+            conn.exec_drop(
+                "INSERT INTO schema_version (version, description, applied_time) values(?, ?, ?)",
+                (
+                    migration.version,
+                    migration.description,
+                    Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+                ),
+            )?;
+            version = migration.version;
+        }
+        Ok(version)
+    }
+    fn flush(&mut self) -> StorageResult<()> {
+        let mut conn = self.pool.get_conn()?;
+        conn.query_drop(r"TRUNCATE TABLE contest_list")?;
+        conn.query_drop(r"TRUNCATE TABLE contest_problems")?;
+        conn.query_drop(r"TRUNCATE TABLE contest_users")?;
+        conn.query_drop(r"TRUNCATE TABLE job_cases")?;
+        conn.query_drop(r"TRUNCATE TABLE job_attempts")?;
+        conn.query_drop(r"TRUNCATE TABLE attempt_cases")?;
+        conn.query_drop(r"TRUNCATE TABLE job_submit")?;
+        conn.query_drop(r"TRUNCATE TABLE joblist")?;
+        conn.query_drop(r"TRUNCATE TABLE userlist")?;
+        conn.query_drop(r"TRUNCATE TABLE user_credentials")?;
+        Ok(())
+    }
+    fn init(&mut self, problem_ids: &[usize]) -> StorageResult<()> {
+        let mut conn = self.pool.get_conn()?;
+        conn.exec_drop(
+            "
+            INSERT INTO userlist (id, name, role)
+            SELECT ?, ?, ?
+            from DUAL
+            where not exists(select * from userlist where id = ?)",
+            (0, "root", Role::Admin.to_string(), 0),
+        )?;
+        // if there is no (0, *) user in mysql, insert (0, root) as the admin
+        for pid in problem_ids {
+            conn.exec_drop(
+                "
+                INSERT INTO contest_problems (id, pid)
+                SELECT ?, ?
+                from DUAL
+                where not exists(select * from contest_problems where id = ? and pid = ?)",
+                (0, pid, 0, pid),
+            )?;
+        }
+        // if there is a problem id not in contest0 in mysql, insert it
+        conn.exec_drop(
+            "
+            INSERT INTO contest_users (id, uid)
+            SELECT ?, ?
+            from DUAL
+            where not exists(select * from contest_users where id = ? and uid = ?)",
+            (0, 0, 0, 0),
+        )?;
+        // if there is no (0, *) user in contest0 in mysql, insert one
+        conn.exec_drop(
+            "
+            INSERT INTO contest_list (id, name, fromtime, totime, submission_limit)
+            SELECT ?, ?, ?, ?, ?
+            from DUAL
+            where not exists(select * from contest_list where id = ?)",
+            (
+                0,
+                "",
+                "0001-01-01T02:00:00.001Z",
+                "9999-12-31T23:59:59.999Z",
+                9999,
+                0,
+            ),
+        )?;
+        // if contest0 is not in mysql, insert it
+        Ok(())
+    }
+    fn repair(&mut self) -> StorageResult<Vec<(&'static str, usize)>> {
+        let mut tx = self.pool.start_transaction(TxOpts::default())?;
+        let mut removed = vec![];
+        for (description, statement) in ORPHANS {
+            tx.query_drop(statement)?;
+            if tx.affected_rows() > 0 {
+                removed.push((*description, tx.affected_rows() as usize));
+            }
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+    fn load_users(&mut self) -> StorageResult<Vec<User>> {
+        let mut conn = self.pool.get_conn()?;
+        let users: Vec<(usize, String, String)> =
+            conn.query("SELECT id, name, role FROM userlist;")?;
+        users
+            .into_iter()
+            .map(|(id, name, role)| {
+                Ok(User {
+                    id: Some(id),
+                    name,
+                    password: None,
+                    role: Some(string2role(&role)?),
+                    waring: None,
+                })
+            })
+            .collect()
+    }
+    fn load_jobs(&mut self) -> StorageResult<Vec<JobResponse>> {
+        let mut conn = self.pool.get_conn()?;
+        let jobs =
+            conn.query("SELECT id, create_time, update_time, state, result, score FROM joblist;")?;
+        let submits = conn.query(
+            "SELECT id, source_code, language, user_id, contest_id, problem_id FROM job_submit;",
+        )?;
+        let allcases =
+            conn.query("SELECT jobid, caseid, result, time, memory, info FROM job_cases;")?;
+        assemble_jobs(jobs, submits, allcases)
+    }
+    fn load_contests(&mut self) -> StorageResult<Vec<Contest>> {
+        let mut conn = self.pool.get_conn()?;
+        let cts = conn.query(
+            "SELECT id, name, fromtime, totime, submission_limit, rate_per_user, rate_per_ip,
+            rate_period FROM contest_list;",
+        )?;
+        let pids = conn.query("SELECT id, pid FROM contest_problems;")?;
+        let uids = conn.query("SELECT id, uid FROM contest_users;")?;
+        Ok(assemble_contests(cts, pids, uids))
+    }
+    fn load_attempts(&mut self) -> StorageResult<Vec<Attempt>> {
+        let mut conn = self.pool.get_conn()?;
+        let attempts =
+            conn.query("SELECT jobid, attempt, judged_time, result, score FROM job_attempts;")?;
+        let allcases = conn.query(
+            "SELECT jobid, attempt, caseid, result, time, memory, info FROM attempt_cases;",
+        )?;
+        assemble_attempts(attempts, allcases)
+    }
+    fn load_credentials(&mut self) -> StorageResult<Vec<Credential>> {
+        let mut conn = self.pool.get_conn()?;
+        let credentials: Vec<(usize, String, String, u32)> =
+            conn.query("SELECT id, salt, hash, iterations FROM user_credentials;")?;
+        Ok(credentials
+            .into_iter()
+            .map(|(user_id, salt, hash, iterations)| Credential {
+                user_id,
+                salt,
+                hash,
+                iterations,
+            })
+            .collect())
+    }
+    fn insert_job(&mut self, job: &JobResponse) -> StorageResult<()> {
+        let mut tx = self.pool.start_transaction(TxOpts::default())?;
+        tx.exec_drop(
+            "
+            INSERT INTO job_submit (id, source_code, language, user_id, contest_id, problem_id)
+            values(?, ?, ?, ?, ?, ?)",
+            (
+                job.id,
+                job.submission.source_code.clone(),
+                job.submission.language.clone(),
+                job.submission.user_id,
+                job.submission.contest_id,
+                job.submission.problem_id,
+            ),
+        )?;
+        // store job_submit
+        for (i, case) in job.cases.iter().enumerate() {
+            tx.exec_drop(
+                "
+                INSERT INTO job_cases (jobid, caseid, result, time, memory, info)
+                values(?, ?, ?, ?, ?, ?)",
+                (
+                    job.id,
+                    i,
+                    case.result.to_string(),
+                    case.time,
+                    case.memory,
+                    case.info.clone(),
+                ),
+            )?;
+        }
+        // store job_cases
+        tx.exec_drop(
+            "
+            INSERT INTO joblist (id, create_time, update_time, state, result, score)
+            values(?, ?, ?, ?, ?, ?)",
+            (
+                job.id,
+                job.created_time.clone(),
+                job.updated_time.clone(),
+                job.state.to_string(),
+                job.result.to_string(),
+                job.score,
+            ),
+        )?;
+        // store job itself
+        tx.commit()?;
+        Ok(())
+    }
+    fn update_job(&mut self, job: &JobResponse) -> StorageResult<()> {
+        let mut tx = self.pool.start_transaction(TxOpts::default())?;
+        Self::replace_job(&mut tx, job)?;
+        tx.commit()?;
+        Ok(())
+    }
+    fn finish_job(&mut self, job: &JobResponse, attempt: &Attempt) -> StorageResult<()> {
+        let mut tx = self.pool.start_transaction(TxOpts::default())?;
+        Self::replace_job(&mut tx, job)?;
+        tx.exec_drop(
+            "
+            INSERT INTO job_attempts (jobid, attempt, judged_time, result, score)
+            values(?, ?, ?, ?, ?)",
+            (
+                attempt.job_id,
+                attempt.id,
+                attempt.judged_time.clone(),
+                attempt.result.to_string(),
+                attempt.score,
+            ),
+        )?;
+        for case in &attempt.cases {
+            tx.exec_drop(
+                "
+                INSERT INTO attempt_cases (jobid, attempt, caseid, result, time, memory, info)
+                values(?, ?, ?, ?, ?, ?, ?)",
+                (
+                    attempt.job_id,
+                    attempt.id,
+                    case.id,
+                    case.result.to_string(),
+                    case.time,
+                    case.memory,
+                    case.info.clone(),
+                ),
+            )?;
+        }
+        // keep the judging as a new attempt
+        tx.commit()?;
+        Ok(())
+    }
+    fn delete_job(&mut self, id: usize) -> StorageResult<()> {
+        let mut tx = self.pool.start_transaction(TxOpts::default())?;
+        tx.exec_drop("delete from joblist where id = ? ", (id,))?;
+        tx.exec_drop("delete from job_submit where id = ? ", (id,))?;
+        tx.exec_drop("delete from job_cases where jobid = ? ", (id,))?;
+        tx.exec_drop("delete from job_attempts where jobid = ? ", (id,))?;
+        tx.exec_drop("delete from attempt_cases where jobid = ? ", (id,))?;
+        tx.commit()?;
+        Ok(())
+    }
+    fn insert_user(&mut self, user: &User, credential: Option<&Credential>) -> StorageResult<()> {
+        let mut tx = self.pool.start_transaction(TxOpts::default())?;
+        tx.exec_drop(
+            "
+            INSERT INTO userlist (id, name, role)
+            values(?, ?, ?)",
+            (
+                user.id.unwrap(),
+                user.name.clone(),
+                user.role.unwrap_or(Role::Contestant).to_string(),
+            ),
+        )?;
+        tx.exec_drop(
+            "
+            INSERT INTO contest_users (id, uid)
+            values(?, ?)",
+            (0, user.id.unwrap()),
+        )?;
+        if let Some(credential) = credential {
+            Self::replace_credential(&mut tx, credential)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+    fn update_user(&mut self, user: &User, credential: Option<&Credential>) -> StorageResult<()> {
+        let mut tx = self.pool.start_transaction(TxOpts::default())?;
+        tx.exec_drop(
+            "UPDATE userlist
+            SET name = ?, role = ?
+            where id = ?",
+            (
+                user.name.clone(),
+                user.role.unwrap_or(Role::Contestant).to_string(),
+                user.id.unwrap(),
+            ),
+        )?;
+        if let Some(credential) = credential {
+            Self::replace_credential(&mut tx, credential)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+    fn insert_contest(&mut self, contest: &Contest) -> StorageResult<()> {
+        let mut tx = self.pool.start_transaction(TxOpts::default())?;
+        tx.exec_drop(
+            "
+            INSERT INTO contest_list (id, name, fromtime, totime, submission_limit,
+            rate_per_user, rate_per_ip, rate_period)
+            values(?, ?, ?, ?, ?, ?, ?, ?)",
+            (
+                contest.id,
+                contest.name.clone(),
+                contest.from.clone(),
+                contest.to.clone(),
+                contest.submission_limit,
+                rate_columns(contest).0,
+                rate_columns(contest).1,
+                rate_columns(contest).2,
+            ),
+        )?;
+        // insert new contest in contest_list in mysql
+        for pid in &contest.problem_ids {
+            tx.exec_drop(
+                "
+                INSERT INTO contest_problems (id, pid)
+                values(?, ?)",
+                (contest.id, pid),
+            )?;
+        }
+        // insert new problems in contest_problems in mysql
+        for uid in &contest.user_ids {
+            tx.exec_drop(
+                "
+                INSERT INTO contest_users (id, uid)
+                values(?, ?)",
+                (contest.id, uid),
+            )?;
+        }
+        // insert new users in contest_users in mysql
+        tx.commit()?;
+        Ok(())
+    }
+    fn update_contest(&mut self, contest: &Contest) -> StorageResult<()> {
+        let mut tx = self.pool.start_transaction(TxOpts::default())?;
+        tx.exec_drop(
+            "UPDATE contest_list
+            SET name = ?, fromtime = ?, totime = ?, submission_limit = ?,
+            rate_per_user = ?, rate_per_ip = ?, rate_period = ?
+            where id = ?",
+            (
+                contest.name.clone(),
+                contest.from.clone(),
+                contest.to.clone(),
+                contest.submission_limit,
+                rate_columns(contest).0,
+                rate_columns(contest).1,
+                rate_columns(contest).2,
+                contest.id,
+            ),
+        )?;
+        // update contest_list in mysql
+        tx.exec_drop("delete from contest_problems where id = ? ", (contest.id,))?;
+        for pid in &contest.problem_ids {
+            tx.exec_drop(
+                "
+                INSERT INTO contest_problems (id, pid)
+                values(?, ?)",
+                (contest.id, pid),
+            )?;
+        }
+        // replace the problem ids
+        tx.exec_drop("delete from contest_users where id = ? ", (contest.id,))?;
+        for uid in &contest.user_ids {
+            tx.exec_drop(
+                "
+                INSERT INTO contest_users (id, uid)
+                values(?, ?)",
+                (contest.id, uid),
+            )?;
+        }
+        // replace the user ids
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+    /*
+        function: to open the sqlite database file, which is created when missing
+        input: path: a &str of the database file
+        output: a SqliteStorage, or an err from sqlite
+    */
+    pub fn open(path: &str) -> StorageResult<Self> {
+        let conn = Connection::open(path)?;
+        Ok(SqliteStorage { conn })
+    }
+    /*
+        function: to insert the cases of a job
+        input: conn: a &Connection, usually a transaction
+               job: a &JobResponse whose cases are to be inserted
+    */
+    fn insert_cases(conn: &Connection, job: &JobResponse) -> StorageResult<()> {
+        for (i, case) in job.cases.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO job_cases (jobid, caseid, result, time, memory, info)
+                values(?, ?, ?, ?, ?, ?)",
+                params![
+                    job.id,
+                    i,
+                    case.result.to_string(),
+                    case.time as i64,
+                    case.memory,
+                    case.info
+                ],
+            )?;
+        }
+        Ok(())
+    }
+    /*
+        function: to insert the row of a job in joblist
+        input: conn: a &Connection, usually a transaction
+               job: a &JobResponse to be inserted
+    */
+    fn insert_joblist(conn: &Connection, job: &JobResponse) -> StorageResult<()> {
+        conn.execute(
+            "INSERT INTO joblist (id, create_time, update_time, state, result, score)
+            values(?, ?, ?, ?, ?, ?)",
+            params![
+                job.id,
+                job.created_time,
+                job.updated_time,
+                job.state.to_string(),
+                job.result.to_string(),
+                job.score
+            ],
+        )?;
+        Ok(())
+    }
+    /*
+        function: to replace the credential of a user
+        input: conn: a &Connection, usually a transaction
+               credential: a &Credential to be stored
+    */
+    fn replace_credential(conn: &Connection, credential: &Credential) -> StorageResult<()> {
+        conn.execute(
+            "DELETE FROM user_credentials where id = ?",
+            params![credential.user_id],
+        )?;
+        conn.execute(
+            "INSERT INTO user_credentials (id, salt, hash, iterations) values(?, ?, ?, ?)",
+            params![
+                credential.user_id,
+                credential.salt,
+                credential.hash,
+                credential.iterations
+            ],
+        )?;
+        Ok(())
+    }
+    /*
+        function: to replace the cases and the row of a job in joblist
+        input: conn: a &Connection, usually a transaction
+               job: a &JobResponse to be stored
+    */
+    fn replace_job(conn: &Connection, job: &JobResponse) -> StorageResult<()> {
+        conn.execute("DELETE FROM job_cases where jobid = ?", params![job.id])?;
+        Self::insert_cases(conn, job)?;
+        conn.execute("DELETE FROM joblist where id = ?", params![job.id])?;
+        Self::insert_joblist(conn, job)?;
+        Ok(())
+    }
+    /*
+        function: to insert the problems and users of a contest
+        input: conn: a &Connection, usually a transaction
+               contest: a &Contest whose problems and users are to be inserted
+    */
+    fn insert_members(conn: &Connection, contest: &Contest) -> StorageResult<()> {
+        for pid in &contest.problem_ids {
+            conn.execute(
+                "INSERT INTO contest_problems (id, pid) values(?, ?)",
+                params![contest.id, pid],
+            )?;
+        }
+        for uid in &contest.user_ids {
+            conn.execute(
+                "INSERT INTO contest_users (id, uid) values(?, ?)",
+                params![contest.id, uid],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn migrate(&mut self) -> StorageResult<u32> {
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS schema_version (
+                version INTEGER NOT NULL PRIMARY KEY, description TEXT NOT NULL,
+                applied_time TEXT NOT NULL
+            )",
+            [],
+        )?;
+        let mut version: u32 = self.conn.query_row(CURRENT_VERSION, [], |row| row.get(0))?;
+        for migration in pending(version)? {
+            log::info!(
+                "Applying migration {}: {}",
+                migration.version,
+                migration.description
+            );
+            let tx = self.conn.transaction()?;
+            for statement in migration.sqlite {
+                tx.execute(statement, [])?;
+            }
+            tx.execute(
+                "INSERT INTO schema_version (version, description, applied_time) values(?, ?, ?)",
+                params![
+                    migration.version,
+                    migration.description,
+                    Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+                ],
+            )?;
+            tx.commit()?;
+            // a migration and its record are applied together or not at all
+            version = migration.version;
+        }
+        Ok(version)
+    }
+    fn flush(&mut self) -> StorageResult<()> {
+        self.conn.execute_batch(
+            "
+            DELETE FROM contest_list;
+            DELETE FROM contest_problems;
+            DELETE FROM contest_users;
+            DELETE FROM job_cases;
+            DELETE FROM job_attempts;
+            DELETE FROM attempt_cases;
+            DELETE FROM job_submit;
+            DELETE FROM joblist;
+            DELETE FROM userlist;
+            DELETE FROM user_credentials;",
+        )?;
+        Ok(())
+    }
+    fn init(&mut self, problem_ids: &[usize]) -> StorageResult<()> {
+        self.conn.execute(
+            "INSERT INTO userlist (id, name, role) SELECT 0, 'root', 'admin'
+            where not exists(select * from userlist where id = 0)",
+            [],
+        )?;
+        for pid in problem_ids {
+            self.conn.execute(
+                "INSERT INTO contest_problems (id, pid) SELECT 0, ?1
+                where not exists(select * from contest_problems where id = 0 and pid = ?1)",
+                params![pid],
+            )?;
+        }
+        self.conn.execute(
+            "INSERT INTO contest_users (id, uid) SELECT 0, 0
+            where not exists(select * from contest_users where id = 0 and uid = 0)",
+            [],
+        )?;
+        self.conn.execute(
+            "INSERT INTO contest_list (id, name, fromtime, totime, submission_limit)
+            SELECT 0, '', '0001-01-01T02:00:00.001Z', '9999-12-31T23:59:59.999Z', 9999
+            where not exists(select * from contest_list where id = 0)",
+            [],
+        )?;
+        Ok(())
+    }
+    fn repair(&mut self) -> StorageResult<Vec<(&'static str, usize)>> {
+        let tx = self.conn.transaction()?;
+        let mut removed = vec![];
+        for (description, statement) in ORPHANS {
+            let count = tx.execute(statement, [])?;
+            if count > 0 {
+                removed.push((*description, count));
+            }
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+    fn load_users(&mut self) -> StorageResult<Vec<User>> {
+        let mut stmt = self.conn.prepare("SELECT id, name, role FROM userlist")?;
+        let users = stmt
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get::<_, String>(2)?))
+            })?
+            .collect::<Result<Vec<(usize, String, String)>, _>>()?;
+        users
+            .into_iter()
+            .map(|(id, name, role)| {
+                Ok(User {
+                    id: Some(id),
+                    name,
+                    password: None,
+                    role: Some(string2role(&role)?),
+                    waring: None,
+                })
+            })
+            .collect()
+    }
+    fn load_jobs(&mut self) -> StorageResult<Vec<JobResponse>> {
+        let jobs = self
+            .conn
+            .prepare("SELECT id, create_time, update_time, state, result, score FROM joblist")?
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let submits = self
+            .conn
+            .prepare(
+                "SELECT id, source_code, language, user_id, contest_id, problem_id FROM job_submit",
+            )?
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let allcases = self
+            .conn
+            .prepare("SELECT jobid, caseid, result, time, memory, info FROM job_cases")?
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get::<_, i64>(3)? as u128,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        assemble_jobs(jobs, submits, allcases)
+    }
+    fn load_contests(&mut self) -> StorageResult<Vec<Contest>> {
+        let cts = self
+            .conn
+            .prepare(
+                "SELECT id, name, fromtime, totime, submission_limit, rate_per_user, rate_per_ip,
+                rate_period FROM contest_list",
+            )?
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                    row.get(7)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let pids = self
+            .conn
+            .prepare("SELECT id, pid FROM contest_problems")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let uids = self
+            .conn
+            .prepare("SELECT id, uid FROM contest_users")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(assemble_contests(cts, pids, uids))
+    }
+    fn load_attempts(&mut self) -> StorageResult<Vec<Attempt>> {
+        let attempts = self
+            .conn
+            .prepare("SELECT jobid, attempt, judged_time, result, score FROM job_attempts")?
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let allcases = self
+            .conn
+            .prepare(
+                "SELECT jobid, attempt, caseid, result, time, memory, info FROM attempt_cases",
+            )?
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get::<_, i64>(4)? as u128,
+                    row.get(5)?,
+                    row.get(6)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        assemble_attempts(attempts, allcases)
+    }
+    fn load_credentials(&mut self) -> StorageResult<Vec<Credential>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, salt, hash, iterations FROM user_credentials")?;
+        let credentials = stmt
+            .query_map([], |row| {
+                Ok(Credential {
+                    user_id: row.get(0)?,
+                    salt: row.get(1)?,
+                    hash: row.get(2)?,
+                    iterations: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<Credential>, _>>()?;
+        Ok(credentials)
+    }
+    fn insert_job(&mut self, job: &JobResponse) -> StorageResult<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO job_submit (id, source_code, language, user_id, contest_id, problem_id)
+            values(?, ?, ?, ?, ?, ?)",
+            params![
+                job.id,
+                job.submission.source_code,
+                job.submission.language,
+                job.submission.user_id,
+                job.submission.contest_id,
+                job.submission.problem_id
+            ],
+        )?;
+        Self::insert_cases(&tx, job)?;
+        Self::insert_joblist(&tx, job)?;
+        tx.commit()?;
+        Ok(())
+    }
+    fn update_job(&mut self, job: &JobResponse) -> StorageResult<()> {
+        let tx = self.conn.transaction()?;
+        Self::replace_job(&tx, job)?;
+        tx.commit()?;
+        Ok(())
+    }
+    fn finish_job(&mut self, job: &JobResponse, attempt: &Attempt) -> StorageResult<()> {
+        let tx = self.conn.transaction()?;
+        Self::replace_job(&tx, job)?;
+        tx.execute(
+            "INSERT INTO job_attempts (jobid, attempt, judged_time, result, score)
+            values(?, ?, ?, ?, ?)",
+            params![
+                attempt.job_id,
+                attempt.id,
+                attempt.judged_time,
+                attempt.result.to_string(),
+                attempt.score
+            ],
+        )?;
+        for case in &attempt.cases {
+            tx.execute(
+                "INSERT INTO attempt_cases (jobid, attempt, caseid, result, time, memory, info)
+                values(?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    attempt.job_id,
+                    attempt.id,
+                    case.id,
+                    case.result.to_string(),
+                    case.time as i64,
+                    case.memory,
+                    case.info
+                ],
+            )?;
+        }
+        // keep the judging as a new attempt
+        tx.commit()?;
+        Ok(())
+    }
+    fn delete_job(&mut self, id: usize) -> StorageResult<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM joblist where id = ?", params![id])?;
+        tx.execute("DELETE FROM job_submit where id = ?", params![id])?;
+        tx.execute("DELETE FROM job_cases where jobid = ?", params![id])?;
+        tx.execute("DELETE FROM job_attempts where jobid = ?", params![id])?;
+        tx.execute("DELETE FROM attempt_cases where jobid = ?", params![id])?;
+        tx.commit()?;
+        Ok(())
+    }
+    fn insert_user(&mut self, user: &User, credential: Option<&Credential>) -> StorageResult<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO userlist (id, name, role) values(?, ?, ?)",
+            params![
+                user.id,
+                user.name,
+                user.role.unwrap_or(Role::Contestant).to_string()
+            ],
+        )?;
+        tx.execute(
+            "INSERT INTO contest_users (id, uid) values(0, ?)",
+            params![user.id],
+        )?;
+        if let Some(credential) = credential {
+            Self::replace_credential(&tx, credential)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+    fn update_user(&mut self, user: &User, credential: Option<&Credential>) -> StorageResult<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "UPDATE userlist SET name = ?, role = ? where id = ?",
+            params![
+                user.name,
+                user.role.unwrap_or(Role::Contestant).to_string(),
+                user.id
+            ],
+        )?;
+        if let Some(credential) = credential {
+            Self::replace_credential(&tx, credential)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+    fn insert_contest(&mut self, contest: &Contest) -> StorageResult<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO contest_list (id, name, fromtime, totime, submission_limit,
+            rate_per_user, rate_per_ip, rate_period)
+            values(?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                contest.id,
+                contest.name,
+                contest.from,
+                contest.to,
+                contest.submission_limit,
+                rate_columns(contest).0,
+                rate_columns(contest).1,
+                rate_columns(contest).2
+            ],
+        )?;
+        Self::insert_members(&tx, contest)?;
+        tx.commit()?;
+        Ok(())
+    }
+    fn update_contest(&mut self, contest: &Contest) -> StorageResult<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "UPDATE contest_list SET name = ?, fromtime = ?, totime = ?, submission_limit = ?,
+            rate_per_user = ?, rate_per_ip = ?, rate_period = ?
+            where id = ?",
+            params![
+                contest.name,
+                contest.from,
+                contest.to,
+                contest.submission_limit,
+                rate_columns(contest).0,
+                rate_columns(contest).1,
+                rate_columns(contest).2,
+                contest.id
+            ],
+        )?;
+        tx.execute(
+            "DELETE FROM contest_problems where id = ?",
+            params![contest.id],
+        )?;
+        tx.execute(
+            "DELETE FROM contest_users where id = ?",
+            params![contest.id],
+        )?;
+        Self::insert_members(&tx, contest)?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/*
+    the version of the snapshot format, to be raised when the layout changes
+*/
+const SNAPSHOT_VERSION: u32 = 1;
+
+/*
+    everything stored by JsonStorage, as written to the snapshot file
+*/
+#[derive(Default, Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    users: BTreeMap<usize, User>,
+    jobs: BTreeMap<usize, JobResponse>,
+    contests: BTreeMap<usize, Contest>,
+    #[serde(default)]
+    attempts: BTreeMap<usize, Vec<Attempt>>,
+    #[serde(default)]
+    credentials: BTreeMap<usize, Credential>,
+}
+
+/*
+    a write recorded in the journal, replaying one twice leaves the same result
+*/
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Entry {
+    Flush,
+    Init { problem_ids: Vec<usize> },
+    PutJob { job: JobResponse },
+    PutAttempt { attempt: Attempt },
+    DeleteJob { id: usize },
+    PutUser { user: User },
+    PutCredential { credential: Credential },
+    PutContest { contest: Contest },
+}
+
+/*
+    a snapshot file plus an append-only journal of the writes since the snapshot,
+    for running without a database service
+*/
+pub struct JsonStorage {
+    path: String,
+    journal: File,
+    data: Snapshot,
+}
+
+impl JsonStorage {
+    /*
+        function: to restore from the snapshot and its journal, then start a new snapshot
+        input: path: a &str of the snapshot file, the journal is kept next to it
+        output: a JsonStorage, or an err when the files cannot be read or written
+    */
+    pub fn open(path: &str) -> StorageResult<Self> {
+        let mut data = match File::open(path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Snapshot {
+                version: SNAPSHOT_VERSION,
+                ..Snapshot::default()
+            },
+            Err(err) => return Err(err.into()),
+        };
+        if data.version > SNAPSHOT_VERSION {
+            return Err(format!(
+                "snapshot version {} is newer than the latest known version {}",
+                data.version, SNAPSHOT_VERSION
+            )
+            .into());
+        }
+        let journal_path = format!("{}.journal", path);
+        if let Ok(file) = File::open(&journal_path) {
+            for (i, line) in BufReader::new(file).lines().enumerate() {
+                match serde_json::from_str::<Entry>(&line?) {
+                    Ok(entry) => Self::apply(&mut data, entry),
+                    Err(err) => {
+                        log::warn!("Journal stops at line {}: {}", i + 1, err);
+                        break;
+                    }
+                }
+            }
+        }
+        // replay the writes since the last snapshot, a torn last line is dropped
+        let journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&journal_path)?;
+        let mut storage = JsonStorage {
+            path: path.to_string(),
+            journal,
+            data,
+        };
+        storage.checkpoint()?;
+        Ok(storage)
+    }
+    /*
+        function: to apply a write to the data
+        input: data: a &mut Snapshot to be changed
+               entry: an Entry of the write
+    */
+    fn apply(data: &mut Snapshot, entry: Entry) {
+        match entry {
+            Entry::Flush => {
+                data.users.clear();
+                data.jobs.clear();
+                data.contests.clear();
+                data.attempts.clear();
+                data.credentials.clear();
+            }
+            Entry::Init { problem_ids } => {
+                data.users.entry(0).or_insert(User {
+                    id: Some(0),
+                    name: String::from("root"),
+                    password: None,
+                    role: Some(Role::Admin),
+                    waring: None,
+                });
+                let contest = data.contests.entry(0).or_insert(Contest {
+                    id: Some(0),
+                    name: String::from(""),
+                    from: String::from("0001-01-01T02:00:00.001Z"),
+                    to: String::from("9999-12-31T23:59:59.999Z"),
+                    problem_ids: vec![],
+                    user_ids: vec![],
+                    submission_limit: 9999,
+                    rate_limit: None,
+                    waring: None,
+                });
+                for pid in problem_ids {
+                    if !contest.problem_ids.contains(&pid) {
+                        contest.problem_ids.push(pid);
+                    }
+                }
+                if !contest.user_ids.contains(&0) {
+                    contest.user_ids.push(0);
+                }
+            }
+            Entry::PutJob { job } => {
+                data.jobs.insert(job.id, job);
+            }
+            Entry::PutAttempt { attempt } => {
+                let attempts = data.attempts.entry(attempt.job_id).or_default();
+                attempts.retain(|old| old.id != attempt.id);
+                attempts.push(attempt);
+            }
+            Entry::DeleteJob { id } => {
+                data.jobs.remove(&id);
+                data.attempts.remove(&id);
+            }
+            Entry::PutUser { user } => {
+                if let Some(id) = user.id {
+                    data.users.insert(id, user);
+                }
+            }
+            Entry::PutCredential { credential } => {
+                data.credentials.insert(credential.user_id, credential);
+            }
+            Entry::PutContest { contest } => {
+                if let Some(id) = contest.id {
+                    data.contests.insert(id, contest);
+                }
+            }
+        }
+    }
+    /*
+        function: to append a write to the journal before applying it
+        input: entry: an Entry of the write
+    */
+    fn write(&mut self, entry: Entry) -> StorageResult<()> {
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        self.journal.write_all(line.as_bytes())?;
+        self.journal.sync_data()?;
+        Self::apply(&mut self.data, entry);
+        Ok(())
+    }
+}
+
+impl Storage for JsonStorage {
+    fn migrate(&mut self) -> StorageResult<u32> {
+        self.data.version = SNAPSHOT_VERSION;
+        Ok(SNAPSHOT_VERSION)
+    }
+    fn flush(&mut self) -> StorageResult<()> {
+        self.write(Entry::Flush)?;
+        self.checkpoint()
+    }
+    fn init(&mut self, problem_ids: &[usize]) -> StorageResult<()> {
+        self.write(Entry::Init {
+            problem_ids: problem_ids.to_vec(),
+        })
+    }
+    fn repair(&mut self) -> StorageResult<Vec<(&'static str, usize)>> {
+        Ok(vec![])
+    }
+    fn load_users(&mut self) -> StorageResult<Vec<User>> {
+        Ok(self
+            .data
+            .users
+            .values()
+            .cloned()
+            .map(|mut user| {
+                if user.role.is_none() {
+                    user.role = Some(if user.id == Some(0) {
+                        Role::Admin
+                    } else {
+                        Role::Contestant
+                    });
+                }
+                user
+            })
+            .collect())
+    }
+    // users in a snapshot written before roles existed
+    fn load_jobs(&mut self) -> StorageResult<Vec<JobResponse>> {
+        Ok(self.data.jobs.values().cloned().collect())
+    }
+    fn load_contests(&mut self) -> StorageResult<Vec<Contest>> {
+        Ok(self.data.contests.values().cloned().collect())
+    }
+    fn load_attempts(&mut self) -> StorageResult<Vec<Attempt>> {
+        let mut attempts: Vec<Attempt> = self.data.attempts.values().flatten().cloned().collect();
+        attempts.sort_by_key(|attempt| (attempt.job_id, attempt.id));
+        Ok(attempts)
+    }
+    fn load_credentials(&mut self) -> StorageResult<Vec<Credential>> {
+        Ok(self.data.credentials.values().cloned().collect())
+    }
+    fn insert_job(&mut self, job: &JobResponse) -> StorageResult<()> {
+        self.write(Entry::PutJob { job: job.clone() })
+    }
+    fn update_job(&mut self, job: &JobResponse) -> StorageResult<()> {
+        self.write(Entry::PutJob { job: job.clone() })
+    }
+    fn finish_job(&mut self, job: &JobResponse, attempt: &Attempt) -> StorageResult<()> {
+        self.write(Entry::PutJob { job: job.clone() })?;
+        self.write(Entry::PutAttempt {
+            attempt: attempt.clone(),
+        })
+    }
+    fn delete_job(&mut self, id: usize) -> StorageResult<()> {
+        self.write(Entry::DeleteJob { id })
+    }
+    fn insert_user(&mut self, user: &User, credential: Option<&Credential>) -> StorageResult<()> {
+        self.write(Entry::PutUser { user: user.clone() })?;
+        if let Some(credential) = credential {
+            self.write(Entry::PutCredential {
+                credential: credential.clone(),
+            })?;
+        }
+        if let (Some(contest), Some(id)) = (self.data.contests.get(&0), user.id) {
+            let mut contest = contest.clone();
+            contest.user_ids.push(id);
+            self.write(Entry::PutContest { contest })?;
+        }
+        // join contest0 as well
+        Ok(())
+    }
+    fn update_user(&mut self, user: &User, credential: Option<&Credential>) -> StorageResult<()> {
+        self.write(Entry::PutUser { user: user.clone() })?;
+        if let Some(credential) = credential {
+            self.write(Entry::PutCredential {
+                credential: credential.clone(),
+            })?;
+        }
+        Ok(())
+    }
+    fn insert_contest(&mut self, contest: &Contest) -> StorageResult<()> {
+        self.write(Entry::PutContest {
+            contest: contest.clone(),
+        })
+    }
+    fn update_contest(&mut self, contest: &Contest) -> StorageResult<()> {
+        self.write(Entry::PutContest {
+            contest: contest.clone(),
+        })
+    }
+    fn checkpoint(&mut self) -> StorageResult<()> {
+        let tmp = format!("{}.tmp", self.path);
+        let mut file = File::create(&tmp)?;
+        serde_json::to_writer(&mut file, &self.data)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        // the old snapshot stays in place until the new one is complete
+        self.journal.set_len(0)?;
+        // a crash before truncating only replays writes already in the snapshot
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MysqlStorage, SqliteStorage, Storage, CURRENT_VERSION, MIGRATIONS};
+    use crate::config::Database;
+    use mysql::prelude::*;
+
+    #[test]
+    fn current_version_of_an_empty_table_is_zero() {
+        let storage = SqliteStorage::open(":memory:").unwrap();
+        storage
+            .conn
+            .execute("CREATE TABLE schema_version (version INTEGER)", [])
+            .unwrap();
+        let version: u32 = storage
+            .conn
+            .query_row(CURRENT_VERSION, [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, 0);
+    }
+
+    #[test]
+    fn sqlite_migrates_once() {
+        let latest = MIGRATIONS.last().unwrap().version;
+        let mut storage = SqliteStorage::open(":memory:").unwrap();
+        assert_eq!(storage.migrate().unwrap(), latest);
+        assert_eq!(storage.migrate().unwrap(), latest);
+    }
+
+    #[test]
+    fn mysql_migrates_once() {
+        // needs a server, given as OJ_TEST_MYSQL_URL of an empty database, skipped otherwise
+        let url = match std::env::var("OJ_TEST_MYSQL_URL") {
+            Ok(url) => url,
+            Err(_) => return,
+        };
+        let latest = MIGRATIONS.last().unwrap().version;
+        let mut storage = MysqlStorage::connect(&url, &Database::default()).unwrap();
+        assert_eq!(storage.migrate().unwrap(), latest);
+        assert_eq!(storage.migrate().unwrap(), latest);
+        let mut conn = storage.pool.get_conn().unwrap();
+        conn.query_drop("DELETE FROM schema_version WHERE version > 1")
+            .unwrap();
+        // as if every migration after the first stopped before it was recorded
+        assert_eq!(storage.migrate().unwrap(), latest);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::structs::{Attempt, Contest, JobResponse, User};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/*
+    the jobs keyed by id, with indexes of job ids by user, problem and contest,
+    and the attempts of each job
+*/
+#[derive(Default)]
+pub struct JobStore {
+    jobs: BTreeMap<usize, JobResponse>,
+    by_user: HashMap<usize, BTreeSet<usize>>,
+    by_problem: HashMap<usize, BTreeSet<usize>>,
+    by_contest: HashMap<usize, BTreeSet<usize>>,
+    attempts: HashMap<usize, Vec<Attempt>>,
+}
+
+impl JobStore {
+    pub fn new() -> Self {
+        JobStore::default()
+    }
+    pub fn get(&self, id: usize) -> Option<&JobResponse> {
+        self.jobs.get(&id)
+    }
+    /*
+        function: to change a job in place
+        input: id: a usize of the job's id
+        output: the job, whose submission must be left unchanged since it is indexed
+    */
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut JobResponse> {
+        self.jobs.get_mut(&id)
+    }
+    /*
+        function: to add a job, or replace the job with the same id
+        input: job: a JobResponse to be stored
+    */
+    pub fn insert(&mut self, job: JobResponse) {
+        if let Some(old) = self.jobs.remove(&job.id) {
+            self.unindex(&old);
+        } // the attempts of the job are kept
+        let id = job.id;
+        let submission = &job.submission;
+        if let Some(uid) = submission.user_id {
+            self.by_user.entry(uid).or_default().insert(id);
+        } // set for every job posted
+        self.by_problem
+            .entry(submission.problem_id)
+            .or_default()
+            .insert(id);
+        self.by_contest
+            .entry(submission.contest_id)
+            .or_default()
+            .insert(id);
+        self.jobs.insert(id, job);
+    }
+    /*
+        function: to remove a job along with its attempts
+        input: id: a usize of the job's id
+        output: the job removed, or None
+    */
+    pub fn remove(&mut self, id: usize) -> Option<JobResponse> {
+        let job = self.jobs.remove(&id)?;
+        self.unindex(&job);
+        self.attempts.remove(&id);
+        Some(job)
+    }
+    /*
+        function: to remove a job from the indexes
+        input: job: a &JobResponse just taken out of jobs
+    */
+    fn unindex(&mut self, job: &JobResponse) {
+        let id = job.id;
+        let submission = &job.submission;
+        for (index, key) in [
+            (&mut self.by_user, submission.user_id),
+            (&mut self.by_problem, Some(submission.problem_id)),
+            (&mut self.by_contest, Some(submission.contest_id)),
+        ] {
+            let key = match key {
+                Some(key) => key,
+                None => continue,
+            };
+            if let Some(ids) = index.get_mut(&key) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    index.remove(&key);
+                }
+            }
+        }
+    }
+    /*
+        function: to get the attempts of a job
+        input: id: a usize of the job's id
+        output: the attempts in the order they were judged, empty if there is none
+    */
+    pub fn attempts(&self, id: usize) -> &[Attempt] {
+        self.attempts
+            .get(&id)
+            .map_or(&[], |attempts| attempts.as_slice())
+    }
+    /*
+        function: to add an attempt of a job, or replace the one with the same id
+        input: attempt: an Attempt of a job in the store
+    */
+    pub fn insert_attempt(&mut self, attempt: Attempt) {
+        if !self.jobs.contains_key(&attempt.job_id) {
+            return;
+        } // a job deleted while judging
+        let attempts = self.attempts.entry(attempt.job_id).or_default();
+        attempts.retain(|old| old.id != attempt.id);
+        attempts.push(attempt);
+        attempts.sort_by_key(|attempt| attempt.id);
+    }
+    /*
+        function: to iterate over all jobs
+        output: an iterator of the jobs in the order of their ids
+    */
+    pub fn iter(&self) -> impl Iterator<Item = &JobResponse> + '_ {
+        self.jobs.values()
+    }
+    /*
+        function: to find the id for a new job
+        output: one past the largest id, deleted jobs leave gaps in the ids
+    */
+    pub fn next_id(&self) -> usize {
+        self.jobs.keys().next_back().map_or(0, |id| id + 1)
+    }
+    /*
+        function: to pick the jobs by their ids in an index
+        input: ids: the ids found in an index, if any
+        output: an iterator of the jobs in the order of their ids
+    */
+    fn pick<'a>(
+        &'a self,
+        ids: Option<&'a BTreeSet<usize>>,
+    ) -> impl Iterator<Item = &'a JobResponse> + 'a {
+        ids.into_iter()
+            .flatten()
+            .filter_map(move |id| self.jobs.get(id))
+    }
+    pub fn by_user(&self, uid: usize) -> impl Iterator<Item = &JobResponse> + '_ {
+        self.pick(self.by_user.get(&uid))
+    }
+    pub fn by_problem(&self, pid: usize) -> impl Iterator<Item = &JobResponse> + '_ {
+        self.pick(self.by_problem.get(&pid))
+    }
+    pub fn by_contest(&self, cid: usize) -> impl Iterator<Item = &JobResponse> + '_ {
+        self.pick(self.by_contest.get(&cid))
+    }
+}
+
+/*
+    the users keyed by id, with an index of ids by name
+*/
+#[derive(Default)]
+pub struct UserStore {
+    users: BTreeMap<usize, User>,
+    by_name: HashMap<String, usize>,
+}
+
+impl UserStore {
+    pub fn new() -> Self {
+        UserStore::default()
+    }
+    pub fn get(&self, id: usize) -> Option<&User> {
+        self.users.get(&id)
+    }
+    pub fn contains(&self, id: usize) -> bool {
+        self.users.contains_key(&id)
+    }
+    /*
+        function: to find a user by name
+        input: name: a &str of the name
+        output: the user's id, or None
+    */
+    pub fn id_of(&self, name: &str) -> Option<usize> {
+        self.by_name.get(name).copied()
+    }
+    /*
+        function: to add a user, or replace the user with the same id
+        input: user: a User with its id set
+    */
+    pub fn insert(&mut self, user: User) {
+        let id = user.id.unwrap();
+        if let Some(old) = self.users.get(&id) {
+            self.by_name.remove(&old.name);
+        }
+        self.by_name.insert(user.name.clone(), id);
+        self.users.insert(id, user);
+    }
+    /*
+        function: to iterate over all users
+        output: an iterator of the users in the order of their ids
+    */
+    pub fn iter(&self) -> impl Iterator<Item = &User> + '_ {
+        self.users.values()
+    }
+    pub fn next_id(&self) -> usize {
+        self.users.keys().next_back().map_or(0, |id| id + 1)
+    }
+}
+
+/*
+    the contests keyed by id
+*/
+#[derive(Default)]
+pub struct ContestStore {
+    contests: BTreeMap<usize, Contest>,
+}
+
+impl ContestStore {
+    pub fn new() -> Self {
+        ContestStore::default()
+    }
+    pub fn get(&self, id: usize) -> Option<&Contest> {
+        self.contests.get(&id)
+    }
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Contest> {
+        self.contests.get_mut(&id)
+    }
+    pub fn contains(&self, id: usize) -> bool {
+        self.contests.contains_key(&id)
+    }
+    /*
+        function: to add a contest, or replace the contest with the same id
+        input: contest: a Contest with its id set
+    */
+    pub fn insert(&mut self, contest: Contest) {
+        self.contests.insert(contest.id.unwrap(), contest);
+    }
+    /*
+        function: to iterate over all contests
+        output: an iterator of the contests in the order of their ids, contest0 first
+    */
+    pub fn iter(&self) -> impl Iterator<Item = &Contest> + '_ {
+        self.contests.values()
+    }
+    pub fn next_id(&self) -> usize {
+        self.contests.keys().next_back().map_or(0, |id| id + 1)
+    }
+}
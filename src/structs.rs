@@ -1,6 +1,9 @@
 use std::usize;
 
-use crate::config::ProblemType;
+use crate::{
+    config::{ProblemType, RateLimit},
+    store::JobStore,
+};
 
 use super::{CONFIG, JOB_LIST, USER_LIST};
 use chrono::NaiveDateTime;
@@ -11,7 +14,7 @@ use strum::{self, Display};
 pub struct JobRequest {
     pub source_code: String,
     pub language: String,
-    pub user_id: usize,
+    pub user_id: Option<usize>,
     pub contest_id: usize,
     pub problem_id: usize,
 }
@@ -91,8 +94,37 @@ pub fn string2result(myresult: &str) -> Result<MyResult, String> {
         _ => Err("Can't transform into MyResult".to_string()),
     }
 }
+/*
+function: to transform a comma separated list of results into MyResults
+input: list: a &str of results, each either in full like Wrong Answer or short like WA
+output: a Ok(Vec<MyResult>), or an err if any of them is not a result
+*/
+pub fn string2results(list: &str) -> Result<Vec<MyResult>, String> {
+    list.split(',')
+        .map(|result| match result {
+            "AC" => Ok(MyResult::Accepted),
+            "CE" => Ok(MyResult::CE),
+            "CS" => Ok(MyResult::CS),
+            "WA" => Ok(MyResult::WA),
+            "RE" => Ok(MyResult::RE),
+            "TLE" => Ok(MyResult::TLE),
+            "MLE" => Ok(MyResult::MLE),
+            "SE" => Ok(MyResult::SE),
+            "SPJE" => Ok(MyResult::SPJE),
+            _ => string2result(result),
+        })
+        .collect()
+}
+/*
+function: to transform a comma separated list of ids into usizes
+input: list: a &str of ids like 1,2,3
+output: a Ok(Vec<usize>), or an err if any of them is not a number
+*/
+pub fn string2ids(list: &str) -> Result<Vec<usize>, std::num::ParseIntError> {
+    list.split(',').map(|id| id.parse::<usize>()).collect()
+}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Case {
     pub id: usize,
     pub result: MyResult,
@@ -123,6 +155,15 @@ pub struct JobArgs {
     pub to: Option<String>,
     pub state: Option<String>,
     pub result: Option<String>,
+    pub updated_from: Option<String>,
+    pub updated_to: Option<String>,
+    pub score_from: Option<String>,
+    pub score_to: Option<String>,
+    pub limit: Option<String>,
+    pub offset: Option<String>,
+    pub cursor: Option<String>,
+    pub order: Option<String>,
+    pub omit: Option<String>,
 }
 /*
 function: to compare whether the first time is later than the second
@@ -142,50 +183,45 @@ pub fn later(mytime: &str, yourtime: &str) -> bool {
 impl JobResponse {
     /*
     function: to check if the problem id is as requested
-    input: pid: an option<String> of the problem id to be compared
-    output: true if the problem id is as requested or when pid is None, otherwise false
+    input: pid: an option<String> of the problem ids to be compared, separated by commas
+    output: true if the problem id is one of those requested or when pid is None, otherwise false
     */
     pub fn pmatch(&self, pid: &Option<String>) -> bool {
         if pid.is_none() {
             true // which means problem id isn't listed in the request for getting a job
         } else {
-            if self.submission.problem_id == pid.clone().unwrap().parse::<usize>().unwrap() {
-                true
-            } else {
-                false
-            }
+            string2ids(pid.as_ref().unwrap())
+                .unwrap()
+                .contains(&self.submission.problem_id)
         }
     }
     /*
     function: to check if the contest id is as requested
-    input: cid: an option<String> of the contest id to be compared
-    output: true if the contest id is as requested or when cid is None, otherwise false
+    input: cid: an option<String> of the contest ids to be compared, separated by commas
+    output: true if the contest id is one of those requested or when cid is None, otherwise false
     */
     pub fn cmatch(&self, cid: &Option<String>) -> bool {
         if cid.is_none() {
             true // which means problem id isn't listed in the request for getting a job
         } else {
-            if self.submission.contest_id == cid.clone().unwrap().parse::<usize>().unwrap() {
-                true
-            } else {
-                false
-            }
+            string2ids(cid.as_ref().unwrap())
+                .unwrap()
+                .contains(&self.submission.contest_id)
         }
     }
     /*
     function: to check if the user id is as requested
-    input: uid: an option<String> of the user id to be compared
-    output: true if the user id is as requested or when uid is None, otherwise false
+    input: uid: an option<String> of the user ids to be compared, separated by commas
+    output: true if the user id is one of those requested or when uid is None, otherwise false
     */
     pub fn umatch(&self, uid: &Option<String>) -> bool {
         if uid.is_none() {
             true // which means problem id isn't listed in the request for getting a job
         } else {
-            if self.submission.user_id == uid.clone().unwrap().parse::<usize>().unwrap() {
-                true
-            } else {
-                false
-            }
+            string2ids(uid.as_ref().unwrap())
+                .unwrap()
+                .iter()
+                .any(|uid| Some(*uid) == self.submission.user_id)
         }
     }
     /*
@@ -197,7 +233,7 @@ impl JobResponse {
         if uname.is_none() {
             true // which means problem id isn't listed in the request for getting a job
         } else {
-            if name(self.submission.user_id) == uname.clone().unwrap() {
+            if self.submission.user_id.map(name) == *uname {
                 true
             } else {
                 false
@@ -270,21 +306,53 @@ impl JobResponse {
     }
     /*
     function: to check if the result is as requested
-    input: result: an option<MyResult> of the result to be compared
-    output: true if the result is as requested or when result is None, otherwise false
+    input: result: an option<String> of the results to be compared, separated by commas
+    output: true if the result is one of those requested or when result is None, otherwise false
     */
     pub fn rmatch(&self, result: &Option<String>) -> bool {
         if result.is_none() {
             true // which means result isn't listed in the request for getting a job
         } else {
-            if self.result == string2result(&result.clone().unwrap()).unwrap() {
-                true
-            } else {
-                false
-            }
+            string2results(result.as_ref().unwrap())
+                .unwrap()
+                .contains(&self.result)
         }
     }
     /*
+    function: to check if the updated time is as updated_from requested
+    input: from: an option<String> of the time string to be compared
+    output: true if the updated_time is later than from or when from is None, otherwise false
+    */
+    pub fn ufmatch(&self, from: &Option<String>) -> bool {
+        if from.is_none() {
+            true // which means updated_from isn't listed in the request for getting a job
+        } else {
+            later(&self.updated_time, from.as_ref().unwrap())
+        }
+    }
+    /*
+    function: to check if the updated time is as updated_to requested
+    input: to: an option<String> of the time string to be compared
+    output: true if to is later than the updated_time or when to is None, otherwise false
+    */
+    pub fn utmatch(&self, to: &Option<String>) -> bool {
+        if to.is_none() {
+            true // which means updated_to isn't listed in the request for getting a job
+        } else {
+            !later(&self.updated_time, to.as_ref().unwrap())
+        }
+    }
+    /*
+    function: to check if the score is in the requested range
+    input: from, to: option<String>s of the lowest and highest score, both included
+    output: true if the score is in the range, a bound that is None is not checked
+    */
+    pub fn scmatch(&self, from: &Option<String>, to: &Option<String>) -> bool {
+        let score = |bound: &Option<String>| bound.as_ref().map(|s| s.parse::<f64>().unwrap());
+        score(from).into_iter().all(|from| self.score >= from)
+            && score(to).into_iter().all(|to| self.score <= to)
+    }
+    /*
     function: to check if the JobResponse is as requested
     input: args: the args listed when getting a job
     output: true if the JobResponse is as requested, otherwise false
@@ -298,6 +366,91 @@ impl JobResponse {
             && self.tmatch(&args.to)
             && self.smatch(&args.state)
             && self.rmatch(&args.result)
+            && self.ufmatch(&args.updated_from)
+            && self.utmatch(&args.updated_to)
+            && self.scmatch(&args.score_from, &args.score_to)
+    }
+}
+
+/*
+    one judging of a job, kept after the job is rejudged
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attempt {
+    pub job_id: usize,
+    pub id: usize,
+    pub judged_time: String,
+    pub result: MyResult,
+    pub score: f64,
+    pub cases: Vec<Case>,
+}
+
+/*
+    a job in a rejudge, with its verdict before the rejudge and the latest one
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejudgedJob {
+    pub id: usize,
+    pub previous_result: MyResult,
+    pub previous_score: f64,
+    pub state: State,
+    pub result: MyResult,
+    pub score: f64,
+}
+
+/*
+    a batch of jobs rejudged together, the jobs still judging are skipped
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rejudge {
+    pub id: usize,
+    pub created_time: String,
+    pub total: usize,
+    pub finished: usize,
+    pub jobs: Vec<RejudgedJob>,
+    pub skipped: Vec<usize>,
+}
+
+impl Rejudge {
+    /*
+    function: to bring the verdicts and the number of finished jobs up to date
+    input: joblist: a &JobStore of all jobs, a job deleted since keeps its last verdict
+    */
+    pub fn progress(&mut self, joblist: &JobStore) {
+        for rejudged in self.jobs.iter_mut() {
+            if let Some(job) = joblist.get(rejudged.id) {
+                rejudged.state = job.state.clone();
+                rejudged.result = job.result.clone();
+                rejudged.score = job.score;
+            }
+        }
+        self.finished = self
+            .jobs
+            .iter()
+            .filter(|job| job.state == State::Finished)
+            .count();
+    }
+}
+
+#[derive(Clone, Copy, Debug, Display, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Role {
+    Admin,
+    ProblemSetter,
+    Contestant,
+}
+/*
+function: to transform a String into Role
+input: role: a &str to be transformed
+output: the corresponding Role or Err
+*/
+pub fn string2role(role: &str) -> Result<Role, String> {
+    match role {
+        "admin" => Ok(Role::Admin),
+        "problem_setter" => Ok(Role::ProblemSetter),
+        "contestant" => Ok(Role::Contestant),
+        _ => Err("Can't transform into Role".to_string()),
     }
 }
 
@@ -305,6 +458,11 @@ impl JobResponse {
 pub struct User {
     pub id: Option<usize>,
     pub name: String,
+    #[serde(default, skip_serializing)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub role: Option<Role>,
+    #[serde(alias = "warning")]
     pub waring: Option<String>,
 }
 
@@ -317,6 +475,9 @@ pub struct Contest {
     pub problem_ids: Vec<usize>,
     pub user_ids: Vec<usize>,
     pub submission_limit: usize,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    #[serde(alias = "warning")]
     pub waring: Option<String>,
 }
 
@@ -352,6 +513,22 @@ pub struct UserRank {
     pub rank: usize,
     pub scores: Vec<f64>,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Sample {
+    pub id: usize,
+    pub input: String,
+    pub answer: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProblemStatement {
+    pub id: usize,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: ProblemType,
+    pub samples: Vec<Sample>,
+}
 /*
     function: to select all the jobs that match the user id and the problem id
     input: uid: a usize of the user id to be matched
@@ -360,13 +537,11 @@ pub struct UserRank {
 */
 pub async fn candidates(uid: usize, pid: usize) -> Vec<JobResponse> {
     let joblist = &JOB_LIST.lock().unwrap();
-    let mut candidates = vec![];
-    for i in 0..joblist.len() {
-        if joblist[i].submission.user_id == uid && joblist[i].submission.problem_id == pid {
-            candidates.push(joblist[i].clone());
-        }
-    }
-    candidates
+    joblist
+        .by_user(uid)
+        .filter(|job| job.submission.problem_id == pid && job.state != State::Canceled)
+        .cloned()
+        .collect()
 }
 /*
     function: to select the latest JobResponse from candidates
@@ -422,13 +597,20 @@ pub async fn highest(candidates: &Vec<JobResponse>) -> (String, f64) {
     output: a String of the user's name or an empty String
 */
 pub fn name(uid: usize) -> String {
-    let userlist = &mut USER_LIST.lock().unwrap();
-    for i in 0..userlist.len() {
-        if userlist[i].id.unwrap() == uid {
-            return userlist[i].name.clone();
-        }
+    let userlist = &USER_LIST.lock().unwrap();
+    match userlist.get(uid) {
+        Some(user) => user.name.clone(),
+        None => String::from(""),
     }
-    String::from("")
+}
+/*
+    function: to get the role of a user according to the id
+    input: uid: a usize of the user id
+    output: Some(Role) of the user, None if not found
+*/
+pub fn role(uid: usize) -> Option<Role> {
+    let userlist = &USER_LIST.lock().unwrap();
+    userlist.get(uid).and_then(|user| user.role)
 }
 /*
     function: to break the tie between the candidates according to submission time
@@ -457,6 +639,8 @@ pub async fn submission_time(
                 user: User {
                     id: Some(candidates[i][j].0),
                     name: name(candidates[i][j].0),
+                    password: None,
+                    role: role(candidates[i][j].0),
                     waring: None,
                 },
                 rank: before + j + 1,
@@ -543,6 +727,8 @@ pub async fn submission_count(
                     user: User {
                         id: Some(same_submits[j][k].0),
                         name: name(same_submits[j][k].0),
+                        password: None,
+                        role: role(same_submits[j][k].0),
                         waring: None,
                     },
                     rank: before + beforee + 1,
@@ -582,6 +768,8 @@ pub async fn user_id(
                 user: User {
                     id: Some(candidates[i][j].0),
                     name: name(candidates[i][j].0),
+                    password: None,
+                    role: role(candidates[i][j].0),
                     waring: None,
                 },
                 rank: before + j + 1,
@@ -619,6 +807,8 @@ pub async fn none(
                 user: User {
                     id: Some(candidates[i][j].0),
                     name: name(candidates[i][j].0),
+                    password: None,
+                    role: role(candidates[i][j].0),
                     waring: None,
                 },
                 rank: before + 1,
@@ -681,13 +871,12 @@ impl Contest {
                         let mut score = 0.0;
                         for l in 1..latest.cases.len() {
                             let mut min = latest.cases[l].time;
-                            for m in 0..joblist.len() {
-                                if joblist[m].submission.problem_id == pid
-                                    && joblist[m].submission.contest_id == self.id.unwrap()
-                                    && joblist[m].result == MyResult::Accepted
-                                    && joblist[m].cases[l].time < min
+                            for job in joblist.by_problem(pid) {
+                                if job.submission.contest_id == self.id.unwrap()
+                                    && job.result == MyResult::Accepted
+                                    && job.cases[l].time < min
                                 {
-                                    min = joblist[m].cases[l].time;
+                                    min = job.cases[l].time;
                                 }
                             }
                             // find the least time of this problem in all the JobResponses
@@ -0,0 +1,201 @@
+use crate::{
+    config::RateLimit,
+    structs::{Case, Contest, JobRequest, JobResponse, MyResult, Role, State, User, UserRank},
+};
+use actix_web::HttpRequest;
+use serde_derive::Serialize;
+
+/* the version of the api a request is served by, set as app data on the /api/v1 scope */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Version {
+    Legacy,
+    V1,
+}
+
+impl Version {
+    /*
+        function: to get the version of the api a request came in by
+        input: req: a &HttpRequest
+        output: Version::V1 under /api/v1, Version::Legacy at the root
+    */
+    pub fn of(req: &HttpRequest) -> Version {
+        req.app_data::<Version>()
+            .copied()
+            .unwrap_or(Version::Legacy)
+    }
+    /*
+        function: to get a body ready to be serialized for this version
+        input: body: a T to be sent
+        output: a Body of T as it is for the legacy routes, or of its v1 type
+    */
+    pub fn body<T: Versioned>(self, body: T) -> Body<T> {
+        match self {
+            Version::Legacy => Body::Legacy(body),
+            Version::V1 => Body::V1(body.v1()),
+        }
+    }
+}
+
+/* a response body that is spelt differently under /api/v1 */
+pub trait Versioned: serde::Serialize {
+    type V1: serde::Serialize;
+    fn v1(self) -> Self::V1;
+}
+
+/* a response body of either version, serialized as the one it holds */
+#[derive(Serialize)]
+#[serde(untagged, bound = "")]
+pub enum Body<T: Versioned> {
+    Legacy(T),
+    V1(T::V1),
+}
+
+/* a User under /api/v1, with waring spelt warning */
+#[derive(Debug, Serialize)]
+pub struct UserV1 {
+    pub id: Option<usize>,
+    pub name: String,
+    pub role: Option<Role>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+/* a Contest under /api/v1, with waring spelt warning */
+#[derive(Debug, Serialize)]
+pub struct ContestV1 {
+    pub id: Option<usize>,
+    pub name: String,
+    pub from: String,
+    pub to: String,
+    pub problem_ids: Vec<usize>,
+    pub user_ids: Vec<usize>,
+    pub submission_limit: usize,
+    pub rate_limit: Option<RateLimit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+/* a JobResponse under /api/v1, leaving out a null warning */
+#[derive(Debug, Serialize)]
+pub struct JobResponseV1 {
+    pub id: usize,
+    pub created_time: String,
+    pub updated_time: String,
+    pub submission: JobRequest,
+    pub state: State,
+    pub result: MyResult,
+    pub score: f64,
+    pub cases: Vec<Case>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+/* a UserRank under /api/v1, holding a UserV1 */
+#[derive(Debug, Serialize)]
+pub struct UserRankV1 {
+    pub user: UserV1,
+    pub rank: usize,
+    pub scores: Vec<f64>,
+}
+
+impl Versioned for User {
+    type V1 = UserV1;
+    fn v1(self) -> UserV1 {
+        UserV1 {
+            id: self.id,
+            name: self.name,
+            role: self.role,
+            warning: self.waring,
+        }
+    }
+}
+
+impl Versioned for Contest {
+    type V1 = ContestV1;
+    fn v1(self) -> ContestV1 {
+        ContestV1 {
+            id: self.id,
+            name: self.name,
+            from: self.from,
+            to: self.to,
+            problem_ids: self.problem_ids,
+            user_ids: self.user_ids,
+            submission_limit: self.submission_limit,
+            rate_limit: self.rate_limit,
+            warning: self.waring,
+        }
+    }
+}
+
+impl Versioned for JobResponse {
+    type V1 = JobResponseV1;
+    fn v1(self) -> JobResponseV1 {
+        JobResponseV1 {
+            id: self.id,
+            created_time: self.created_time,
+            updated_time: self.updated_time,
+            submission: self.submission,
+            state: self.state,
+            result: self.result,
+            score: self.score,
+            cases: self.cases,
+            warning: self.warning,
+        }
+    }
+}
+
+impl Versioned for UserRank {
+    type V1 = UserRankV1;
+    fn v1(self) -> UserRankV1 {
+        UserRankV1 {
+            user: self.user.v1(),
+            rank: self.rank,
+            scores: self.scores,
+        }
+    }
+}
+
+impl<T: Versioned> Versioned for Vec<T> {
+    type V1 = Vec<T::V1>;
+    fn v1(self) -> Vec<T::V1> {
+        self.into_iter().map(Versioned::v1).collect()
+    }
+}
+
+impl<T: Versioned> Versioned for Option<T> {
+    type V1 = Option<T::V1>;
+    fn v1(self) -> Option<T::V1> {
+        self.map(Versioned::v1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alice() -> User {
+        User {
+            id: Some(1),
+            name: String::from("alice"),
+            password: Some(String::from("secret")),
+            role: None,
+            waring: None,
+        }
+    }
+
+    #[test]
+    fn legacy_body_keeps_waring() {
+        let text = serde_json::to_string(&Version::Legacy.body(alice())).unwrap();
+        assert_eq!(text, r#"{"id":1,"name":"alice","role":null,"waring":null}"#);
+    }
+
+    #[test]
+    fn v1_body_spells_warning() {
+        let text = serde_json::to_string(&Version::V1.body(vec![alice()])).unwrap();
+        assert_eq!(text, r#"[{"id":1,"name":"alice","role":null}]"#);
+        let mut user = alice();
+        user.waring = Some(String::from("not persisted"));
+        let text = serde_json::to_string(&Version::V1.body(user)).unwrap();
+        assert!(text.ends_with(r#""warning":"not persisted"}"#));
+    }
+}
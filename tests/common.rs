@@ -4,12 +4,15 @@ use reqwest::blocking::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
 use serde_json;
 use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
 use std::env::consts::EXE_EXTENSION;
 use std::fs::{File, OpenOptions};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
-use std::sync::Once;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Once};
+use std::thread::JoinHandle;
 use std::time::Duration;
 
 // The code was originally written by Jack O'Connor (@oconnor663)
@@ -73,12 +76,16 @@ struct TestRequest {
     path: String,
     method: String,
     content: Value,
+    #[serde(default)]
+    headers: BTreeMap<String, String>, // "{{name}}" in a value is replaced by a captured variable
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct TestResponse {
     status: u16,
     content: Value,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
 }
 
 fn _default_true() -> bool {
@@ -87,12 +94,33 @@ fn _default_true() -> bool {
 fn _default_false() -> bool {
     false
 }
+// turn a stream of server-sent events into [{"event": name, "data": json}]
+fn parse_events(text: &str) -> Value {
+    let mut events = vec![];
+    for block in text.split("\n\n").filter(|block| !block.trim().is_empty()) {
+        let mut event = Value::Null;
+        let mut data = String::new();
+        for line in block.lines() {
+            if let Some(name) = line.strip_prefix("event: ") {
+                event = Value::String(name.to_string());
+            } else if let Some(line) = line.strip_prefix("data: ") {
+                data += line;
+            }
+        }
+        let data: Value = serde_json::from_str(&data).expect("event data is not JSON");
+        events.push(serde_json::json!({ "event": event, "data": data }));
+    }
+    Value::Array(events)
+}
 fn _default_timeout() -> u64 {
     3000
 }
 fn _default_poll_count() -> u64 {
     5
 }
+fn _default_repeat() -> u64 {
+    1
+}
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct HttpComm {
@@ -106,6 +134,22 @@ struct HttpComm {
     poll_count: u64,
     #[serde(default = "_default_false")]
     restart_server: bool, // restart server before sending request
+    #[serde(default = "_default_false")]
+    terminate_server: bool, // send SIGTERM to server before sending request
+    #[serde(default = "_default_false")]
+    hangup_server: bool, // send SIGHUP to server before sending request
+    #[serde(default = "_default_false")]
+    hangup_meanwhile: bool, // keep sending SIGHUP to server while sending request
+    #[serde(default = "_default_repeat")]
+    repeat: u64, // send request this many times, each response is checked
+    #[serde(default)]
+    address: Option<String>, // "host:port" to send request to, instead of the bound address
+    #[serde(default)]
+    config: Option<String>, // case whose config replaces the one the server reads, before sending request
+    #[serde(default)]
+    capture: BTreeMap<String, String>, // variable name -> JSON pointer into the response body
+    #[serde(default = "_default_false")]
+    events: bool, // the response is a stream of server-sent events, read till it ends
 }
 
 pub struct TestCase {
@@ -117,6 +161,9 @@ pub struct TestCase {
     stdout_file: PathBuf,
     stderr_file: PathBuf,
     http_file: PathBuf,
+    variables: HashMap<String, String>, // captured from earlier responses
+    terminated: bool,                   // the server got SIGTERM and is shutting down by itself
+    config_file: PathBuf,               // the config the server reads
 }
 
 impl TestCase {
@@ -135,6 +182,15 @@ impl TestCase {
             config["server"]["bind_address"].as_str().unwrap(),
             config["server"]["bind_port"]
         );
+        let data: Vec<HttpComm> = serde_json::from_reader(File::open(data_file).unwrap()).unwrap();
+        let config_file = if data.iter().any(|c| c.config.is_some()) {
+            // the config is replaced while running, so the server reads a copy
+            let live_file = case_dir.join(format!("{}.live.json", name));
+            std::fs::copy(&config_file, &live_file).unwrap();
+            live_file
+        } else {
+            config_file
+        };
 
         Self {
             name: name.to_string(),
@@ -143,15 +199,44 @@ impl TestCase {
                 config_file.to_str().unwrap().to_string(),
                 "--flush-data".to_string(),
             ],
-            data: serde_json::from_reader(File::open(data_file).unwrap()).unwrap(),
+            data,
             prefix,
             running_process: None,
             stdout_file,
             stderr_file,
             http_file,
+            variables: HashMap::new(),
+            terminated: false,
+            config_file,
         }
     }
 
+    pub fn keep_data(mut self) -> Self {
+        // start the server without --flush-data, for cases that prepare the database beforehand
+        self.arguments.retain(|x| x != "--flush-data");
+        self
+    }
+
+    pub fn args(mut self, args: &[&str]) -> Self {
+        // start the server with more arguments, the requests follow --bind-address and --bind-port
+        let (mut address, mut port) = {
+            let bound = self.prefix.trim_start_matches("http://");
+            let (address, port) = bound.rsplit_once(':').unwrap();
+            (address.to_string(), port.to_string())
+        };
+        for pair in args.windows(2) {
+            match pair[0] {
+                "--bind-address" => address = pair[1].to_string(),
+                "--bind-port" => port = pair[1].to_string(),
+                _ => {}
+            }
+        }
+        self.prefix = format!("http://{}:{}", address, port);
+        self.arguments
+            .extend(args.iter().map(|arg| arg.to_string()));
+        self
+    }
+
     fn log_and_send(
         &self,
         req: RequestBuilder,
@@ -239,6 +324,7 @@ impl TestCase {
                 .as_str(),
             );
         self.running_process = Some(command);
+        self.terminated = false;
         // sleep 1 second for server startup
         std::thread::sleep(Duration::from_secs(1));
 
@@ -262,6 +348,16 @@ impl TestCase {
 
     fn kill_server(&mut self) {
         if let Some(mut child) = self.running_process.take() {
+            if self.terminated {
+                // wait for a graceful shutdown to finish
+                for _ in 0..60 {
+                    if let Ok(Some(_)) = child.try_wait() {
+                        return;
+                    }
+                    std::thread::sleep(Duration::from_secs(1));
+                }
+                panic!("case {} incorrect: server did not shut down", self.name);
+            }
             child.kill().expect(
                 format!("case {} incorrect: cannot kill server process", self.name).as_str(),
             );
@@ -269,21 +365,48 @@ impl TestCase {
     }
 
     fn send_request_and_compare_response(&mut self, c: &HttpComm) -> Value {
+        if c.terminate_server {
+            if let Some(child) = &self.running_process {
+                Command::new("kill")
+                    .args(&["-TERM", &child.id().to_string()])
+                    .status()
+                    .ok();
+            }
+            self.terminated = true;
+            // give the server a moment to start shutting down
+            std::thread::sleep(Duration::from_millis(500));
+        }
+        if let Some(name) = &c.config {
+            let case_dir = Path::new("tests").join("cases");
+            std::fs::copy(
+                case_dir.join(format!("{}.config.json", name)),
+                &self.config_file,
+            )
+            .unwrap();
+        }
+        if c.hangup_server {
+            if let Some(child) = &self.running_process {
+                Command::new("kill")
+                    .args(&["-HUP", &child.id().to_string()])
+                    .status()
+                    .ok();
+            }
+            // give the server a moment to reload
+            std::thread::sleep(Duration::from_millis(500));
+        }
         if c.restart_server {
             self.kill_server();
             // remove --flush-data before restarting server, then add it back
             let old_arguments = self.arguments.clone();
-            self.arguments.remove(
-                self.arguments
-                    .iter()
-                    .position(|x| x == "--flush-data")
-                    .unwrap(),
-            );
+            self.arguments.retain(|x| x != "--flush-data");
             self.start_server(true);
             self.arguments = old_arguments;
         }
 
-        let url = format!("{}/{}", &self.prefix, &c.request.path);
+        let url = match &c.address {
+            Some(address) => format!("http://{}/{}", address, &c.request.path),
+            None => format!("{}/{}", &self.prefix, &c.request.path),
+        };
         let method =
             reqwest::Method::from_bytes(&c.request.method.to_uppercase().as_bytes()).unwrap();
 
@@ -291,6 +414,13 @@ impl TestCase {
             let mut request = CLIENT
                 .request(method.clone(), url)
                 .timeout(Duration::from_millis(c.timeout));
+            for (name, value) in &c.request.headers {
+                let mut value = value.clone();
+                for (variable, captured) in &self.variables {
+                    value = value.replace(&format!("{{{{{}}}}}", variable), captured);
+                }
+                request = request.header(name.as_str(), value);
+            }
             if let reqwest::Method::GET = method {
                 // no json body
             } else {
@@ -306,6 +436,26 @@ impl TestCase {
                 "case {} incorrect: wrong status code",
                 self.name
             );
+            for (name, value) in &c.response.headers {
+                assert_eq!(
+                    resp.headers()
+                        .get(name.as_str())
+                        .and_then(|got| got.to_str().ok()),
+                    Some(value.as_str()),
+                    "case {} incorrect: wrong header {}",
+                    self.name,
+                    name
+                );
+            }
+            if c.events && code == 200 {
+                let text = resp.text().expect(
+                    format!("case {} incorrect: cannot read the event stream", self.name).as_str(),
+                );
+                let json = parse_events(&text);
+                serde_json::to_writer(&http_file, &json).ok();
+                writeln!(http_file).ok();
+                return json;
+            }
             let json: Value = resp.json().expect(
                 format!(
                     "case {} incorrect: cannot decode response body as JSON, status code is {}",
@@ -365,9 +515,35 @@ impl TestCase {
                 self.name, error
             );
         }
+
+        // remember values for later requests
+        for (variable, pointer) in &c.capture {
+            let value = match body.pointer(pointer) {
+                Some(Value::String(value)) => value.clone(),
+                Some(value) => value.to_string(),
+                None => panic!(
+                    "case {} incorrect: nothing at {} to capture",
+                    self.name, pointer
+                ),
+            };
+            self.variables.insert(variable.clone(), value);
+        }
         body
     }
 
+    fn keep_hanging_up(&self) -> (Arc<AtomicBool>, JoinHandle<()>) {
+        // send SIGHUP to server again and again on another thread, till the flag is set
+        let pid = self.running_process.as_ref().unwrap().id().to_string();
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        let handle = std::thread::spawn(move || {
+            while !flag.load(Ordering::SeqCst) {
+                Command::new("kill").args(["-HUP", &pid]).status().ok();
+            }
+        });
+        (stop, handle)
+    }
+
     pub fn run(&mut self) -> Vec<Value> {
         self.start_server(false);
         // send requests sequentially
@@ -375,7 +551,22 @@ impl TestCase {
             .data
             .clone()
             .iter()
-            .map(|d| self.send_request_and_compare_response(d))
+            .map(|d| {
+                let hangups = if d.hangup_meanwhile {
+                    Some(self.keep_hanging_up())
+                } else {
+                    None
+                };
+                let mut body = Value::Null;
+                for _ in 0..d.repeat {
+                    body = self.send_request_and_compare_response(d);
+                }
+                if let Some((stop, handle)) = hangups {
+                    stop.store(true, Ordering::SeqCst);
+                    handle.join().unwrap();
+                }
+                body
+            })
             .collect();
         self.kill_server();
         res
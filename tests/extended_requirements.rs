@@ -0,0 +1,508 @@
+mod common;
+use common::TestCase;
+
+#[test]
+fn test_ext_01_sample_hidden_cases() {
+    // check sample and hidden cases
+    // 1. samples are shown in the problem statement
+    // 2. a wrong sample case comes with a diff snippet
+    // 3. details of a hidden case are redacted
+    TestCase::read("ext_01_sample_hidden_cases").run();
+}
+
+#[test]
+fn test_ext_02_schema_migrations() {
+    // check that a new database gets its schema on first start
+    // 1. the tables are created, so a user survives a restart
+    // 2. every migration is recorded in schema_version
+    let path = "./tests/cases/ext_02_schema_migrations.db";
+    let _ = std::fs::remove_file(path);
+    TestCase::read("ext_02_schema_migrations").run();
+    let conn = rusqlite::Connection::open(path).unwrap();
+    let versions: Vec<u32> = conn
+        .prepare("SELECT version FROM schema_version ORDER BY version")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(versions, vec![1, 2, 3, 4, 5, 6, 7]);
+}
+
+#[test]
+fn test_ext_03_orphaned_rows() {
+    // check the startup consistency check
+    // 1. a job without its submission is dropped instead of breaking the job list
+    // 2. cases and contest members without their owner are removed
+    let path = "./tests/cases/ext_03_orphaned_rows.db";
+    let _ = std::fs::remove_file(path);
+    let conn = rusqlite::Connection::open(path).unwrap();
+    conn.execute_batch(
+        "
+        CREATE TABLE joblist (
+            id INTEGER, create_time TEXT, update_time TEXT, state TEXT, result TEXT, score REAL
+        );
+        CREATE TABLE job_cases (
+            jobid INTEGER, caseid INTEGER, result TEXT, time INTEGER, memory REAL, info TEXT
+        );
+        CREATE TABLE contest_users (id INTEGER, uid INTEGER);
+        INSERT INTO joblist VALUES (
+            5, '2022-08-27T02:05:29.000Z', '2022-08-27T02:05:30.000Z', 'Finished', 'Accepted', 100
+        );
+        INSERT INTO job_cases VALUES (6, 0, 'Compilation Success', 0, 0, '');
+        INSERT INTO contest_users VALUES (3, 0);",
+    )
+    .unwrap();
+    TestCase::read("ext_03_orphaned_rows").keep_data().run();
+    let count = |sql: &str| -> u32 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+    assert_eq!(count("SELECT COUNT(*) FROM joblist"), 1);
+    assert_eq!(count("SELECT COUNT(*) FROM job_cases WHERE jobid <> 0"), 0);
+    assert_eq!(count("SELECT COUNT(*) FROM contest_users WHERE id = 3"), 0);
+}
+
+#[test]
+fn test_ext_04_reload_after_deletion() {
+    // check that jobs are joined with their submissions by id when loaded
+    // 1. rows stored in a different order than the job ids still match up
+    // 2. the gap left by deleted job 1 does not shift the later jobs
+    let path = "./tests/cases/ext_04_reload_after_deletion.db";
+    let _ = std::fs::remove_file(path);
+    let conn = rusqlite::Connection::open(path).unwrap();
+    conn.execute_batch(
+        r#"
+        CREATE TABLE joblist (
+            id INTEGER, create_time TEXT, update_time TEXT, state TEXT, result TEXT, score REAL
+        );
+        CREATE TABLE job_submit (
+            id INTEGER, source_code TEXT, language TEXT,
+            user_id INTEGER, contest_id INTEGER, problem_id INTEGER
+        );
+        CREATE TABLE job_cases (
+            jobid INTEGER, caseid INTEGER, result TEXT, time INTEGER, memory REAL, info TEXT
+        );
+        INSERT INTO joblist VALUES
+            (2, '2022-08-27T02:05:31.000Z', '2022-08-27T02:05:32.000Z', 'Finished', 'Accepted', 100),
+            (0, '2022-08-27T02:05:29.000Z', '2022-08-27T02:05:30.000Z', 'Finished', 'Accepted', 100);
+        INSERT INTO job_submit VALUES
+            (0, 'fn main() { println!("Hello World!"); }', 'Rust', 0, 0, 0),
+            (2, 'fn main() { print!("Hello World!\n"); }', 'Rust', 0, 0, 0);
+        INSERT INTO job_cases VALUES
+            (2, 1, 'Accepted', 1000, 0, ''),
+            (0, 0, 'Compilation Success', 0, 0, ''),
+            (2, 0, 'Compilation Success', 0, 0, ''),
+            (0, 1, 'Accepted', 1000, 0, '');"#,
+    )
+    .unwrap();
+    TestCase::read("ext_04_reload_after_deletion")
+        .keep_data()
+        .run();
+}
+
+#[test]
+fn test_ext_05_resume_unfinished_jobs() {
+    // check that jobs interrupted by a shutdown are judged after restart
+    // 1. a running job and a queueing job are both judged again
+    // 2. their results are stored, so they stay finished after another restart
+    let path = "./tests/cases/ext_05_resume_unfinished_jobs.db";
+    let _ = std::fs::remove_file(path);
+    let conn = rusqlite::Connection::open(path).unwrap();
+    conn.execute_batch(
+        r#"
+        CREATE TABLE joblist (
+            id INTEGER, create_time TEXT, update_time TEXT, state TEXT, result TEXT, score REAL
+        );
+        CREATE TABLE job_submit (
+            id INTEGER, source_code TEXT, language TEXT,
+            user_id INTEGER, contest_id INTEGER, problem_id INTEGER
+        );
+        CREATE TABLE job_cases (
+            jobid INTEGER, caseid INTEGER, result TEXT, time INTEGER, memory REAL, info TEXT
+        );
+        INSERT INTO joblist VALUES
+            (0, '2022-08-27T02:05:29.000Z', '2022-08-27T02:05:30.000Z', 'Running', 'Running', 0),
+            (1, '2022-08-27T02:05:30.000Z', '2022-08-27T02:05:30.000Z', 'Queueing', 'Waiting', 0);
+        INSERT INTO job_submit VALUES
+            (0, 'fn main() { println!("Hello World!"); }', 'Rust', 0, 0, 0),
+            (1, 'fn main() { println!("Hello World!"); }', 'Rust', 0, 0, 0);
+        INSERT INTO job_cases VALUES
+            (0, 0, 'Compilation Success', 1000, 0, ''),
+            (0, 1, 'Running', 0, 0, ''),
+            (1, 0, 'Waiting', 0, 0, ''),
+            (1, 1, 'Waiting', 0, 0, '');"#,
+    )
+    .unwrap();
+    TestCase::read("ext_05_resume_unfinished_jobs")
+        .keep_data()
+        .run();
+}
+
+#[test]
+fn test_ext_06_json_snapshot() {
+    // check persistence with a json snapshot instead of a database
+    // 1. users, contests and jobs survive a restart through the journal alone
+    // 2. the snapshot is versioned and holds everything after the restart
+    let path = "./tests/cases/ext_06_json_snapshot.db.json";
+    let _ = std::fs::remove_file(path);
+    let _ = std::fs::remove_file(format!("{}.journal", path));
+    TestCase::read("ext_06_json_snapshot").run();
+    let snapshot: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(path).unwrap()).unwrap();
+    assert_eq!(snapshot["version"], 1);
+    assert_eq!(snapshot["users"]["1"]["name"], "alice");
+    assert_eq!(snapshot["contests"]["1"]["name"], "weekly");
+    assert_eq!(snapshot["jobs"]["0"]["result"], "Accepted");
+}
+
+#[test]
+fn test_ext_07_job_pagination() {
+    // check paging through the job list
+    // 1. limit, cursor and offset pick a slice of the jobs, in either order
+    // 2. the source code and cases can be left out of the list
+    let pages = TestCase::read("ext_07_job_pagination").run();
+    assert_eq!(pages[3].as_array().unwrap().len(), 2);
+    assert_eq!(pages[4].as_array().unwrap().len(), 1);
+    assert_eq!(pages[5].as_array().unwrap().len(), 1);
+    let job = &pages[6][0];
+    assert!(job.get("cases").is_none());
+    assert!(job["submission"].get("source_code").is_none());
+}
+
+#[test]
+fn test_ext_08_job_filters() {
+    // check the filters on the job list
+    // 1. results, users and problems can be listed, results in full or short
+    // 2. jobs can be picked by a score range and by updated time
+    let lists = TestCase::read("ext_08_job_filters").run();
+    let count = |i: usize| lists[i].as_array().unwrap().len();
+    assert_eq!(count(4), 2);
+    assert_eq!(count(5), 2);
+    assert_eq!(count(6), 1);
+    assert_eq!(count(7), 2);
+    assert_eq!(count(8), 0);
+}
+
+#[test]
+fn test_ext_09_bulk_rejudge() {
+    // check rejudging every job matching the filters
+    // 1. the matching jobs are queued again, with their previous verdicts recorded
+    // 2. the progress of the rejudge follows the jobs until all are finished
+    TestCase::read("ext_09_bulk_rejudge").run();
+}
+
+#[test]
+fn test_ext_10_rejudge_history() {
+    // check that every judging of a job is kept
+    // 1. the first judging is stored as an attempt and survives a restart
+    // 2. after the answer is fixed, a rejudge adds an attempt and reports the changed verdict
+    let path = "./tests/cases/ext_10_rejudge_history.db";
+    let _ = std::fs::remove_file(path);
+    TestCase::read("ext_10_rejudge_history").run();
+    TestCase::read("ext_10_rejudge_history_fixed")
+        .keep_data()
+        .run();
+}
+
+#[test]
+fn test_ext_11_job_cancel() {
+    // check canceling jobs
+    // 1. a job is canceled before it is finished, and stays in the job list
+    // 2. a programme that never ends is stopped, so the next job is judged in time
+    // 3. canceled jobs count neither towards the submission limit nor in the ranklist
+    TestCase::read("ext_11_job_cancel").run();
+}
+
+#[test]
+fn test_ext_12_authentication() {
+    // check logging in with tokens
+    // 1. requests without a valid token are rejected, the exit endpoint included
+    // 2. a job belongs to the user logged in, whatever user id the body claims
+    // 3. a token is gone after logging out, and passwords survive a restart
+    let path = "./tests/cases/ext_12_authentication.db";
+    let _ = std::fs::remove_file(path);
+    TestCase::read("ext_12_authentication").run();
+}
+
+#[test]
+fn test_ext_13_roles() {
+    // check what each role may do
+    // 1. root is the admin, who creates users and gives them roles
+    // 2. problem setters run contests and rejudges, contestants cannot
+    // 3. contestants only see their own source code
+    // 4. the admins see hidden cases in full, contestants only their results
+    let bodies = TestCase::read("ext_13_roles").run();
+    assert!(bodies[13][1]["submission"].get("source_code").is_none());
+    assert!(bodies[14]["submission"].get("source_code").is_none());
+    assert_ne!(bodies[23]["cases"][1]["time"], 0);
+    assert_eq!(bodies[24]["cases"][1]["time"], 0);
+    assert_eq!(bodies[24]["cases"][1]["result"], "Accepted");
+}
+
+#[test]
+fn test_ext_14_graceful_shutdown() {
+    // check shutting down on SIGTERM
+    // 1. no submission is taken while shutting down
+    // 2. the running job finishes before the server stops
+    // 3. the queued job is kept and judged after restart
+    let path = "./tests/cases/ext_14_graceful_shutdown.db";
+    let _ = std::fs::remove_file(path);
+    let bodies = TestCase::read("ext_14_graceful_shutdown").run();
+    assert_eq!(bodies[5].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_ext_15_rate_limit() {
+    // check limiting the rate of submissions
+    // 1. a user over the global limit gets ERR_RATE_LIMIT and when to retry, others are not hit
+    // 2. the limit of a contest counts per ip, and only its own submissions
+    // 3. the limit of a contest survives a restart
+    // 4. behind a trusted proxy, the client is told by X-Forwarded-For
+    let path = "./tests/cases/ext_15_rate_limit.db";
+    let _ = std::fs::remove_file(path);
+    TestCase::read("ext_15_rate_limit").run();
+}
+
+#[test]
+fn test_ext_16_source_limits() {
+    // check the limits on submitted source code
+    // 1. empty and binary sources are rejected
+    // 2. a source over the size limit of its language is rejected
+    // 3. a body over the payload limit is refused before it is parsed
+    TestCase::read("ext_16_source_limits").run();
+}
+
+#[test]
+fn test_ext_17_error_messages() {
+    // check the error responses
+    // 1. the message names the id, name or argument at fault
+    // 2. invalid arguments are answered with 400 and missing ones with 404
+    // 3. a body that cannot be parsed is answered with an error as well
+    // 4. without authentication, a job has to name its user
+    let bodies = TestCase::read("ext_17_error_messages").run();
+    assert!(bodies[7]["message"].as_str().unwrap().contains("language"));
+    assert!(bodies[18]["message"].as_str().unwrap().contains("user_id"));
+}
+
+/*
+    to check a json value against a schema of the OpenAPI document: the required fields are
+    present, no field is left undocumented, and enums hold
+*/
+fn conforms(spec: &serde_json::Value, schema: &serde_json::Value, value: &serde_json::Value) {
+    if let Some(path) = schema["$ref"].as_str() {
+        let target = spec.pointer(path.trim_start_matches('#')).unwrap();
+        return conforms(spec, target, value);
+    }
+    if value.is_null() && schema["nullable"] == true {
+        return;
+    }
+    for inner in schema["allOf"].as_array().into_iter().flatten() {
+        conforms(spec, inner, value);
+    }
+    if let Some(values) = schema["enum"].as_array() {
+        assert!(
+            values.contains(value),
+            "{} not in {}",
+            value,
+            schema["enum"]
+        );
+    }
+    match schema["type"].as_str() {
+        Some("object") => {
+            let object = value.as_object();
+            assert!(object.is_some(), "{} not an object", value);
+            let object = object.unwrap();
+            for field in schema["required"].as_array().into_iter().flatten() {
+                assert!(
+                    object.contains_key(field.as_str().unwrap()),
+                    "{} missing",
+                    field
+                );
+            }
+            if let Some(properties) = schema["properties"].as_object() {
+                for (field, inner) in object {
+                    let property = properties.get(field);
+                    assert!(property.is_some(), "{} undocumented", field);
+                    conforms(spec, property.unwrap(), inner);
+                }
+            }
+        }
+        Some("array") => {
+            assert!(value.is_array(), "{} not an array", value);
+            for item in value.as_array().unwrap() {
+                conforms(spec, &schema["items"], item);
+            }
+        }
+        Some("integer") => assert!(value.is_u64() || value.is_i64(), "{} not an integer", value),
+        Some("number") => assert!(value.is_number(), "{} not a number", value),
+        Some("string") => assert!(value.is_string(), "{} not a string", value),
+        _ => {}
+    }
+}
+
+#[test]
+fn test_ext_18_openapi() {
+    // check the OpenAPI document
+    // 1. it is served as written, and lists exactly the routes of the handlers
+    // 2. the responses of the server fit the schemas of their routes
+    let bodies = TestCase::read("ext_18_openapi").run();
+    let spec = &bodies[0];
+    let text = std::fs::read_to_string("src/openapi.json").unwrap();
+    assert_eq!(
+        spec,
+        &serde_json::from_str::<serde_json::Value>(&text).unwrap()
+    );
+    let mut routes = vec![];
+    for file in ["src/api.rs", "src/auth.rs", "src/openapi.rs"] {
+        for line in std::fs::read_to_string(file).unwrap().lines() {
+            for method in ["get", "post", "put", "delete"] {
+                if let Some(rest) = line.strip_prefix(&format!("#[{}(\"", method)) {
+                    let path = rest.trim_end_matches("\")]").to_string();
+                    routes.push((path, method.to_string()));
+                }
+            }
+        }
+    }
+    let mut documented = vec![];
+    for (path, item) in spec["paths"].as_object().unwrap() {
+        for method in item.as_object().unwrap().keys() {
+            documented.push((path.clone(), method.clone()));
+        }
+    }
+    routes.sort();
+    documented.sort();
+    assert_eq!(routes, documented);
+    let ok = |path: &str, method: &str| {
+        &spec["paths"][path][method]["responses"]["200"]["content"]["application/json"]["schema"]
+    };
+    conforms(spec, ok("/users", "post"), &bodies[1]);
+    conforms(spec, ok("/contests", "post"), &bodies[2]);
+    conforms(spec, ok("/jobs", "post"), &bodies[3]);
+    conforms(spec, ok("/contests/{cid}/ranklist", "get"), &bodies[4]);
+    conforms(spec, ok("/jobs/{jobid}/attempts", "get"), &bodies[5]);
+    conforms(spec, ok("/rejudges", "post"), &bodies[6]);
+    conforms(spec, ok("/problems", "get"), &bodies[7]);
+    let error = &spec["paths"]["/jobs/{jobid}"]["get"]["responses"]["404"];
+    let error = spec.pointer(error["$ref"].as_str().unwrap().trim_start_matches('#'));
+    conforms(
+        spec,
+        &error.unwrap()["content"]["application/json"]["schema"],
+        &bodies[8],
+    );
+}
+
+#[test]
+fn test_ext_19_api_versions() {
+    // check the routes under /api/v1 along with the legacy ones
+    // 1. both serve the same data, the legacy routes with their original fields
+    // 2. under /api/v1, waring is spelt warning and a null warning is left out
+    // 3. the OpenAPI document has the v1 schemas along with the legacy ones
+    let bodies = TestCase::read("ext_19_api_versions").run();
+    for i in [1, 2, 3, 4, 6, 10] {
+        assert!(!bodies[i].to_string().contains("\"waring\""));
+    }
+    assert!(bodies[0].get("waring").is_some());
+    assert!(bodies[4][0].get("warning").is_none());
+    assert!(bodies[10][0]["user"].get("warning").is_none());
+    let schemas = &bodies[9]["components"]["schemas"];
+    let user = &schemas["UserV1"]["properties"];
+    assert!(user.get("warning").is_some() && user.get("waring").is_none());
+    assert!(schemas["User"]["properties"].get("waring").is_some());
+}
+
+#[test]
+fn test_ext_20_job_events() {
+    // follow a job as server-sent events
+    // 1. the first event is the job as it is, then each case judged comes as a case event
+    // 2. the stream ends with the job finished, and a finished job is sent once
+    // 3. following a job not found answers 404
+    let bodies = TestCase::read("ext_20_job_events").run();
+    let events = bodies[2].as_array().unwrap();
+    assert_eq!(events[0]["event"], "job");
+    assert_ne!(events[0]["data"]["state"], "Finished");
+    let last = events.last().unwrap();
+    assert_eq!(last["event"], "job");
+    assert_eq!(last["data"]["state"], "Finished");
+    assert_eq!(last["data"]["result"], "Accepted");
+    let cases: Vec<&serde_json::Value> = events
+        .iter()
+        .filter(|event| event["event"] == "case")
+        .map(|event| &event["data"])
+        .collect();
+    assert!(cases
+        .iter()
+        .any(|case| case["id"] == 0 && case["result"] == "Compilation Success"));
+    assert!(cases
+        .iter()
+        .any(|case| case["id"] == 1 && case["result"] == "Accepted"));
+    assert_eq!(bodies[3].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn test_ext_21_config_reload() {
+    // check reloading the config file while running
+    // 1. a valid config takes effect on POST /internal/reload, and on SIGHUP
+    // 2. an invalid config is rejected and the old one stays
+    // 3. removing a problem that a job refers to is refused
+    let bodies = TestCase::read("ext_21_config_reload").run();
+    assert_eq!(bodies[0].as_array().unwrap().len(), 1);
+    assert_eq!(bodies[1]["problems"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_ext_22_listen() {
+    // check the addresses the server listens on
+    // 1. --bind-port on the command line overrides the port in the config
+    // 2. an extra listen address answers the same server
+    // 3. a stale file at the unix socket path is replaced by the socket
+    use std::os::unix::fs::FileTypeExt;
+    let path = "./tests/cases/ext_22_listen.sock";
+    std::fs::write(path, "stale").unwrap();
+    TestCase::read("ext_22_listen")
+        .args(&["--bind-port", "12346"])
+        .run();
+    let socket = std::fs::metadata(path).unwrap().file_type().is_socket();
+    let _ = std::fs::remove_file(path);
+    assert!(socket);
+}
+
+#[test]
+fn test_ext_23_password_upgrade() {
+    // check the hashes of passwords
+    // 1. a new password is hashed with pbkdf2, keeping its work factor
+    // 2. a password hashed the old way still logs in, and is hashed again with pbkdf2
+    let path = "./tests/cases/ext_23_password_upgrade.db";
+    let _ = std::fs::remove_file(path);
+    TestCase::read("ext_23_password_upgrade").run();
+    let iterations = || -> u32 {
+        let conn = rusqlite::Connection::open(path).unwrap();
+        conn.query_row(
+            "SELECT iterations FROM user_credentials WHERE id = 0",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap()
+    };
+    assert_eq!(iterations(), 100_000);
+    rusqlite::Connection::open(path)
+        .unwrap()
+        .execute(
+            "UPDATE user_credentials SET salt = '00ff', iterations = 0,
+            hash = 'a80b2cc4dcc7666f1b7dd753dd4ba5c430a23e5bb11c1676314518896e124565'
+            WHERE id = 0",
+            [],
+        )
+        .unwrap();
+    // sha256 of the salt and "correct horse"
+    TestCase::read("ext_23_password_upgrade_legacy")
+        .keep_data()
+        .run();
+    assert_eq!(iterations(), 100_000);
+}
+
+#[test]
+fn test_ext_24_reload_race() {
+    // post contests while the config is reloaded again and again
+    // 1. no request hangs on the locks taken by the reload
+    // 2. every contest posted is kept
+    let bodies = TestCase::read("ext_24_reload_race").run();
+    assert_eq!(bodies[1].as_array().unwrap().len(), 100);
+}